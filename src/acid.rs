@@ -0,0 +1,167 @@
+// Reference image tests for dmg-acid2 and cgb-acid2.
+//
+// The ROMs are not part of the repo. Put them, together with their
+// reference PNGs, into roms/ (or the directory in $ACID2_ROMS):
+//
+//     dmg-acid2.gb   dmg-acid2.png
+//     cgb-acid2.gbc  cgb-acid2.png
+//
+// Both tests run under `cargo test` and skip with a message when their ROM
+// is missing. With the ROM in place a missing reference fails the test, and
+// so does the ROM itself until the CPU implements every opcode it uses. On
+// a mismatch the frame and a diff image are written to target/acid2/.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::console::Console;
+use crate::image::ghosting::Ghosting;
+use crate::image::ColorType;
+use crate::image::DmgOutput;
+use crate::image::Image;
+use crate::test_support::test_rom;
+
+// Both ROMs finish within a few frames, this only catches hangs.
+const MAX_FRAMES : u64 = 60 * 10;
+
+#[allow(clippy::upper_case_acronyms)]
+pub enum Reference
+{
+    PNG(PathBuf),
+    HASH(u64)
+}
+
+fn rom_dir() -> PathBuf
+{
+    match std::env::var("ACID2_ROMS")
+    {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_)  => Path::new(env!("CARGO_MANIFEST_DIR")).join("roms")
+    }
+}
+
+fn output_path(name : &str) -> String
+{
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("acid2");
+    std::fs::create_dir_all(&dir).expect("create target/acid2");
+    dir.join(name).to_string_lossy().into_owned()
+}
+
+// Runs the ROM to its LD B,B and returns the frame.
+fn capture(rom : &Path) -> Image
+{
+    assert!(rom.exists(), "{} not found", rom.display());
+
+    let mut console = Console::new();
+    console.set_trace(false);
+    console.set_ghosting(Ghosting::OFF);
+    assert!(console.load(&rom.to_string_lossy()), "failed to load {}", rom.display());
+    assert!(console.run_until_breakpoint(MAX_FRAMES), "{} never reached LD B,B", rom.display());
+    console.screenshot(1, DmgOutput::RGB)
+}
+
+pub fn check(name : &str, actual : &Image, reference : &Reference)
+{
+    let actual_path = output_path(&format!("{}-actual.png", name));
+    match reference
+    {
+        Reference::HASH(hash) =>
+        {
+            if actual.hash() != *hash
+            {
+                actual.save(&actual_path).expect("write actual frame");
+                panic!("{}: frame hash {:016X}, expected {:016X}, frame in {}", name, actual.hash(), hash, actual_path);
+            }
+        },
+        Reference::PNG(path) =>
+        {
+            let expected = Image::load(&path.to_string_lossy())
+                .unwrap_or_else(|e| panic!("{}: failed to read reference {}: {}", name, path.display(), e));
+            assert!(expected.width == actual.width && expected.height == actual.height,
+                    "{}: reference is {}x{}, frame is {}x{}",
+                    name, expected.width, expected.height, actual.width, actual.height);
+
+            let (count, diff) = actual.diff(&expected);
+            if count != 0
+            {
+                let diff_path = output_path(&format!("{}-diff.png", name));
+                actual.save(&actual_path).expect("write actual frame");
+                diff.save(&diff_path).expect("write diff image");
+                panic!("{}: {} pixels differ, see {} and {}", name, count, actual_path, diff_path);
+            }
+        }
+    }
+}
+
+fn acid2(rom : &str, reference : &str)
+{
+    let dir  = rom_dir();
+    let path = dir.join(rom);
+    if !path.exists()
+    {
+        eprintln!("skipping {}: {} not found", rom, path.display());
+        return;
+    }
+
+    let frame = capture(&path);
+    let name  = rom.split('.').next().unwrap_or(rom);
+    check(name, &frame, &Reference::PNG(dir.join(reference)));
+}
+
+#[test]
+fn dmg_acid2()
+{
+    acid2("dmg-acid2.gb", "dmg-acid2.png");
+}
+
+#[test]
+fn cgb_acid2()
+{
+    acid2("cgb-acid2.gbc", "cgb-acid2.png");
+}
+
+// ==========================
+// Harness self checks
+// ==========================
+
+#[test]
+fn breakpoint_stops_run()
+{
+    // LD A,0x91; LDH (0x40),A; LD B,B
+    let rom   = test_rom("breakpoint", &[0x3E, 0x91, 0xE0, 0x40, 0x40]);
+    let frame = capture(&rom);
+    std::fs::remove_file(&rom).ok();
+
+    let mut white = Image::new(frame.width, frame.height, ColorType::GRAY2);
+    white.data.fill(3);
+    check("breakpoint", &frame, &Reference::HASH(white.hash()));
+
+    let (count, _) = frame.diff(&white);
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn missing_breakpoint_times_out()
+{
+    // JP 0x0150
+    let rom         = test_rom("timeout", &[0xC3, 0x50, 0x01]);
+    let mut console = Console::new();
+    console.set_trace(false);
+    assert!(console.load(&rom.to_string_lossy()));
+    std::fs::remove_file(&rom).ok();
+
+    assert!(!console.run_until_breakpoint(2));
+}
+
+#[test]
+fn diff_marks_changed_pixels()
+{
+    let a     = Image::new(4, 4, ColorType::RGB);
+    let mut b = Image::new(4, 4, ColorType::RGB);
+    b.put(1, 2, [0xFF, 0xFF, 0xFF]);
+
+    let (count, diff) = a.diff(&b);
+    assert_eq!(count, 1);
+    assert_eq!(&diff.data[(2 * 4 + 1) * 3..(2 * 4 + 2) * 3], &[0xFF, 0x00, 0x00]);
+    assert_ne!(a.hash(), b.hash());
+}
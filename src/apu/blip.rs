@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use crate::timer::CLOCK_HZ;
+
+// Band-limited step synthesis. Instead of sampling the output, every change
+// of level is added as a delta spread over a few output samples by a
+// band-limited impulse at its exact sub-sample position. Summing the deltas
+// back up gives steps without the aliasing of point sampling.
+const WIDTH  : usize = 16;
+const PHASES : usize = 64;
+
+// Passband edge as a fraction of the output rate.
+const CUTOFF : f64 = 0.45;
+
+// Output rates the resampler supports. Near CLOCK_HZ / 2 one T-cycle
+// would reach past the pending window.
+pub const MIN_RATE : f64 = 8_000.0;
+pub const MAX_RATE : f64 = 384_000.0;
+
+pub fn valid_rate(rate : f64) -> bool
+{
+    (MIN_RATE..=MAX_RATE).contains(&rate)
+}
+
+// N outputs sharing one timeline.
+pub struct Blip<const N : usize>
+{
+    rate     : f64,
+    // Output samples per T-cycle.
+    step     : f64,
+    // Position of the current T-cycle within the first pending sample.
+    time     : f64,
+    level    : [f32; N],
+    sum      : [f32; N],
+    pending  : VecDeque<[f32; N]>,
+    kernel   : Vec<[f32; WIDTH]>,
+    highpass : HighPass<N>
+}
+
+impl<const N : usize> Blip<N>
+{
+    pub fn new(rate : f64, cgb : bool) -> Self
+    {
+        let mut blip = Blip
+        {
+            rate     : 0.0,
+            step     : 0.0,
+            time     : 0.0,
+            level    : [0.0; N],
+            sum      : [0.0; N],
+            pending  : VecDeque::from(vec![[0.0; N]; WIDTH + 2]),
+            kernel   : kernel(),
+            highpass : HighPass::new(rate, cgb)
+        };
+        blip.set_rate(rate);
+        blip
+    }
+
+    pub fn rate(&self) -> f64 { self.rate }
+
+    // Can change between calls, for dynamic rate control. Callers check
+    // valid_rate(), anything else is clamped into range.
+    pub fn set_rate(&mut self, rate : f64)
+    {
+        let rate  = if rate.is_nan() { MIN_RATE } else { rate.clamp(MIN_RATE, MAX_RATE) };
+        self.rate = rate;
+        self.step = rate / CLOCK_HZ as f64;
+        self.highpass.set_rate(rate);
+    }
+
+    pub fn set_model(&mut self, cgb : bool)
+    {
+        self.highpass.set_model(cgb);
+    }
+
+    // Level from `offset` T-cycles after the current time on.
+    pub fn set(&mut self, offset : u32, level : [f32; N])
+    {
+        if level == self.level
+        {
+            return;
+        }
+
+        let position = self.time + offset as f64 * self.step;
+        let first    = position as usize;
+        let phase    = ((position - first as f64) * PHASES as f64) as usize;
+        let kernel   = &self.kernel[phase.min(PHASES - 1)];
+
+        for (i, (new, old)) in level.iter().zip(self.level).enumerate()
+        {
+            let delta = new - old;
+            if delta == 0.0
+            {
+                continue;
+            }
+            for (tap, weight) in kernel.iter().enumerate()
+            {
+                self.pending[first + tap][i] += delta * weight;
+            }
+        }
+        self.level = level;
+    }
+
+    // Moves the current time on, calling `emit` for every finished sample.
+    pub fn advance(&mut self, cycles : u32, mut emit : impl FnMut([f32; N]))
+    {
+        self.time += cycles as f64 * self.step;
+        while self.time >= 1.0
+        {
+            let deltas = self.pending.pop_front().unwrap_or([0.0; N]);
+            self.pending.push_back([0.0; N]);
+            self.time -= 1.0;
+
+            for (sum, delta) in self.sum.iter_mut().zip(deltas)
+            {
+                *sum += delta;
+            }
+            emit(self.highpass.apply(self.sum));
+        }
+    }
+}
+
+// Integrated windowed sinc per sub-sample phase, each phase summing to 1 so
+// a step always settles at exactly its delta.
+fn kernel() -> Vec<[f32; WIDTH]>
+{
+    let mut kernel = Vec::with_capacity(PHASES);
+    for phase in 0..PHASES
+    {
+        let offset = phase as f64 / PHASES as f64;
+        let mut taps = [0.0f64; WIDTH];
+        for (tap, value) in taps.iter_mut().enumerate()
+        {
+            // Centered on WIDTH / 2, which delays the output by that many samples.
+            let x      = tap as f64 - (WIDTH / 2) as f64 - offset;
+            let sinc   = if x == 0.0 { 2.0 * CUTOFF } else { (2.0 * PI * CUTOFF * x).sin() / (PI * x) };
+            let window = 0.5 + 0.5 * (PI * x / (WIDTH / 2) as f64).cos();
+            *value = if x.abs() < (WIDTH / 2) as f64 { sinc * window } else { 0.0 };
+        }
+
+        let sum = taps.iter().sum::<f64>();
+        kernel.push(taps.map(|value| (value / sum) as f32));
+    }
+    kernel
+}
+
+// ==========================
+// High-pass
+// ==========================
+
+// Per T-cycle charge factors of the output capacitor.
+const DMG_CHARGE : f64 = 0.999958;
+const CGB_CHARGE : f64 = 0.998943;
+
+// The capacitor in series with the output, it slowly charges towards the
+// DC level and so removes the DACs' offset like on hardware.
+pub struct HighPass<const N : usize>
+{
+    rate      : f64,
+    cgb       : bool,
+    charge    : f32,
+    capacitor : [f32; N]
+}
+
+impl<const N : usize> HighPass<N>
+{
+    pub fn new(rate : f64, cgb : bool) -> Self
+    {
+        let mut highpass = HighPass
+        {
+            rate,
+            cgb,
+            charge    : 0.0,
+            capacitor : [0.0; N]
+        };
+        highpass.update();
+        highpass
+    }
+
+    pub fn set_rate(&mut self, rate : f64)
+    {
+        self.rate = rate;
+        self.update();
+    }
+
+    pub fn set_model(&mut self, cgb : bool)
+    {
+        self.cgb = cgb;
+        self.update();
+    }
+
+    fn update(&mut self)
+    {
+        let base    = if self.cgb { CGB_CHARGE } else { DMG_CHARGE };
+        self.charge = base.powf(CLOCK_HZ as f64 / self.rate) as f32;
+    }
+
+    pub fn apply(&mut self, input : [f32; N]) -> [f32; N]
+    {
+        let mut output = [0.0; N];
+        for i in 0..N
+        {
+            output[i]         = input[i] - self.capacitor[i];
+            self.capacitor[i] = input[i] - output[i] * self.charge;
+        }
+        output
+    }
+}
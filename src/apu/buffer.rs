@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+// Stereo frames waiting for the host. When the host falls behind, the
+// oldest frames are dropped.
+pub struct SampleBuffer
+{
+    frames   : VecDeque<[f32; 2]>,
+    capacity : usize,
+    dropped  : u64
+}
+
+impl SampleBuffer
+{
+    pub fn new(capacity : usize) -> Self
+    {
+        SampleBuffer
+        {
+            frames   : VecDeque::with_capacity(capacity),
+            capacity : capacity.max(1),
+            dropped  : 0
+        }
+    }
+
+    pub fn len(&self) -> usize { self.frames.len() }
+    pub fn is_empty(&self) -> bool { self.frames.is_empty() }
+    pub fn capacity(&self) -> usize { self.capacity }
+    pub fn dropped(&self) -> u64 { self.dropped }
+
+    // 0.0 empty, 1.0 full.
+    pub fn fill(&self) -> f32
+    {
+        self.frames.len() as f32 / self.capacity as f32
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.frames.clear();
+    }
+
+    pub fn push(&mut self, frame : [f32; 2])
+    {
+        if self.frames.len() == self.capacity
+        {
+            self.frames.pop_front();
+            self.dropped += 1;
+        }
+        self.frames.push_back(frame);
+    }
+
+    // Interleaved left/right into `out`, returns the frames written.
+    pub fn read_f32(&mut self, out : &mut [f32]) -> usize
+    {
+        let count = self.frames.len().min(out.len() / 2);
+        for (chunk, frame) in out.chunks_exact_mut(2).zip(self.frames.drain(..count))
+        {
+            chunk.copy_from_slice(&frame);
+        }
+        count
+    }
+
+    pub fn read_i16(&mut self, out : &mut [i16]) -> usize
+    {
+        let count = self.frames.len().min(out.len() / 2);
+        for (chunk, frame) in out.chunks_exact_mut(2).zip(self.frames.drain(..count))
+        {
+            chunk[0] = to_i16(frame[0]);
+            chunk[1] = to_i16(frame[1]);
+        }
+        count
+    }
+}
+
+pub fn to_i16(sample : f32) -> i16
+{
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
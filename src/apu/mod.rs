@@ -0,0 +1,746 @@
+pub mod blip;
+pub mod buffer;
+pub mod noise;
+pub mod pulse;
+pub mod scope;
+pub mod units;
+pub mod wave;
+
+use crate::apu::blip::Blip;
+use crate::apu::buffer::SampleBuffer;
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::scope::Channel;
+use crate::apu::scope::ChannelState;
+use crate::apu::scope::Scope;
+use crate::apu::scope::Visualization;
+use crate::apu::scope::Voice;
+use crate::apu::wave::Wave;
+use crate::timer::CLOCK_HZ;
+
+// 0xFF10 - 0xFF14 : NR10-NR14 - CH1 Pulse with sweep
+// 0xFF16 - 0xFF19 : NR21-NR24 - CH2 Pulse
+// 0xFF1A - 0xFF1E : NR30-NR34 - CH3 Wave
+// 0xFF20 - 0xFF23 : NR41-NR44 - CH4 Noise
+// 0xFF24          : NR50 - Master volume
+// 0xFF25          : NR51 - Panning
+// 0xFF26          : NR52 - Power and channel status
+// 0xFF30 - 0xFF3F : Wave RAM
+
+const NR50 : usize = 0x14;
+const NR51 : usize = 0x15;
+const NR52 : usize = 0x16;
+
+// Host buffer size in seconds of audio.
+const BUFFER_SECONDS : f64 = 0.5;
+
+// Capture frames: left, right, then CH1-CH4 on their own.
+pub const CAPTURE_CHANNELS : usize = 6;
+
+// Gain of the single channel captures. A lone DAC swings over the full
+// -1..1 range, half of that leaves headroom while the high-pass settles.
+const STEM_GAIN : f32 = 0.5;
+
+// Bits that always read back as 1, indexed from 0xFF10.
+const READ_MASK : [u8; 0x20] =
+[
+    0x80, 0x3F, 0x00, 0xFF, 0xBF,
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF,
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF,
+    0xFF, 0xFF, 0x00, 0x00, 0xBF,
+    0x00, 0x00, 0x70,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF
+];
+
+// Register state left behind by the boot ROM, triggers excluded.
+const POST_BOOT : [u8; 0x16] =
+[
+    0x80, 0xBF, 0xF3, 0xFF, 0x3F,
+    0xFF, 0x3F, 0x00, 0xFF, 0x3F,
+    0x7F, 0xFF, 0x9F, 0xFF, 0x3F,
+    0xFF, 0xFF, 0x00, 0x00, 0x3F,
+    0x77, 0xF3
+];
+
+pub struct APU
+{
+    ch1 : Pulse,
+    ch2 : Pulse,
+    ch3 : Wave,
+    ch4 : Noise,
+
+    // NR10-NR51 as written, for reading back.
+    regs  : [u8; 0x20],
+    power : bool,
+    cgb   : bool,
+
+    // Next frame sequencer step, 0-7.
+    frame_step : u8,
+
+    // Mixer controls, stems and the scope still see every channel.
+    muted    : [bool; 4],
+    solo     : [bool; 4],
+    scope    : Scope,
+    triggers : [u32; 4],
+
+    // Host output, None until a sample rate is set.
+    output  : Option<Blip<2>>,
+    samples : SampleBuffer,
+
+    // Recording tap, independent of the host output.
+    capture  : Option<Blip<CAPTURE_CHANNELS>>,
+    captured : Vec<[f32; CAPTURE_CHANNELS]>,
+
+    // Outputs as of the last level change. Register writes can change the
+    // levels without any channel stepping, they mark them dirty.
+    last_outputs : [Option<u8>; 4],
+    dirty        : bool,
+
+    // T-cycles since power on, timestamps for the register log.
+    cycles : u64,
+    log    : Option<Vec<RegWrite>>
+}
+
+// A write to 0xFF10-0xFF3F.
+#[derive(Copy, Clone)]
+pub struct RegWrite
+{
+    pub cycle   : u64,
+    pub address : u16,
+    pub value   : u8
+}
+
+impl APU
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        let mut apu = APU
+        {
+            ch1 : Pulse::new(true),
+            ch2 : Pulse::new(false),
+            ch3 : Wave::new(),
+            ch4 : Noise::new(),
+
+            regs  : [0; 0x20],
+            power : true,
+            cgb   : false,
+
+            frame_step : 0,
+
+            muted    : [false; 4],
+            solo     : [false; 4],
+            scope    : Scope::new(),
+            triggers : [0; 4],
+
+            output  : None,
+            samples : SampleBuffer::new(0),
+
+            capture  : None,
+            captured : Vec::new(),
+
+            last_outputs : [None; 4],
+            dirty        : true,
+
+            cycles : 0,
+            log    : None
+        };
+
+        for (i, value) in POST_BOOT.iter().enumerate()
+        {
+            apu.write_reg(0xFF10 + i as u16, *value);
+        }
+        apu
+    }
+
+    // Back to the post-boot sound state. Mixer settings, the host output,
+    // the capture and log taps and the cycle count carry over, the log
+    // gets the new state so it stays replayable.
+    pub fn reset(&mut self)
+    {
+        let old = std::mem::replace(self, APU::new());
+        self.cgb      = old.cgb;
+        self.muted    = old.muted;
+        self.solo     = old.solo;
+        self.triggers = old.triggers;
+        self.output   = old.output;
+        self.samples  = old.samples;
+        self.capture  = old.capture;
+        self.captured = old.captured;
+        self.cycles   = old.cycles;
+
+        self.log = old.log.map(|mut log|
+        {
+            let cycle = self.cycles;
+            log.extend(self.state_writes().into_iter().map(|(address, value)| RegWrite { cycle, address, value }));
+            log
+        });
+    }
+
+    pub fn cgb(&self) -> bool { self.cgb }
+    pub fn set_cgb(&mut self, cgb : bool)
+    {
+        self.cgb = cgb;
+        if let Some(output) = &mut self.output
+        {
+            output.set_model(cgb);
+        }
+        if let Some(capture) = &mut self.capture
+        {
+            capture.set_model(cgb);
+        }
+    }
+
+    pub fn power(&self) -> bool { self.power }
+
+    pub fn read_reg(&self, address : u16) -> u8
+    {
+        match address
+        {
+            0xFF26 =>
+            {
+                ((self.power as u8) << 7) | READ_MASK[NR52] | self.status()
+            },
+            0xFF10..=0xFF2F =>
+            {
+                let index = (address - 0xFF10) as usize;
+                self.regs[index] | READ_MASK[index]
+            },
+            0xFF30..=0xFF3F => self.ch3.read_ram((address - 0xFF30) as usize, self.cgb),
+            _ => 0xFF
+        }
+    }
+
+    pub fn write_reg(&mut self, address : u16, value : u8)
+    {
+        self.dirty = true;
+        if let Some(log) = &mut self.log
+        {
+            log.push(RegWrite { cycle : self.cycles, address, value });
+        }
+
+        match address
+        {
+            0xFF26 => self.set_power(value & 0x80 != 0),
+            0xFF30..=0xFF3F => self.ch3.write_ram((address - 0xFF30) as usize, value, self.cgb),
+            0xFF10..=0xFF25 =>
+            {
+                if !self.power
+                {
+                    self.write_length_off(address, value);
+                    return;
+                }
+
+                let index = (address - 0xFF10) as usize;
+                self.regs[index] = value;
+
+                // Enabling the length counter in the first half of a length
+                // period clocks it once more.
+                let extra = self.frame_step & 1 == 1;
+                let reg   = (index % 5) as u8;
+                if reg == 4 && value & 0x80 != 0 && index < 0x14
+                {
+                    self.triggers[index / 5] += 1;
+                    if index == 0x0E && !self.cgb
+                    {
+                        self.ch3.corrupt_on_retrigger();
+                    }
+                }
+                match index
+                {
+                    0x00..=0x04 => self.ch1.write(reg, value, extra),
+                    0x05..=0x09 => self.ch2.write(reg, value, extra),
+                    0x0A..=0x0E => self.ch3.write(reg, value, extra),
+                    0x0F..=0x13 => self.ch4.write(reg, value, extra),
+                    _           => {}
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // While powered off the DMG still takes length data, the CGB nothing.
+    fn write_length_off(&mut self, address : u16, value : u8)
+    {
+        if self.cgb
+        {
+            return;
+        }
+
+        match address
+        {
+            0xFF11 => self.ch1.length.load((value & 0x3F) as u16),
+            0xFF16 => self.ch2.length.load((value & 0x3F) as u16),
+            0xFF1B => self.ch3.length.load(value as u16),
+            0xFF20 => self.ch4.length.load((value & 0x3F) as u16),
+            _      => {}
+        }
+    }
+
+    fn set_power(&mut self, on : bool)
+    {
+        if on == self.power
+        {
+            return;
+        }
+        self.power = on;
+
+        if on
+        {
+            self.frame_step = 0;
+            return;
+        }
+
+        // Everything but wave RAM is cleared. The DMG keeps its length
+        // counters.
+        self.regs = [0; 0x20];
+        self.ch1.power_off();
+        self.ch2.power_off();
+        self.ch3.power_off();
+        self.ch4.power_off();
+
+        if self.cgb
+        {
+            self.ch1.length.reset();
+            self.ch2.length.reset();
+            self.ch3.length.reset();
+            self.ch4.length.reset();
+        }
+    }
+
+    // PCM12/PCM34 (CGB): the digital outputs of two channels each, the
+    // lower numbered one in the low nibble.
+    pub fn read_pcm(&self, address : u16) -> u8
+    {
+        let outputs = self.channel_outputs().map(|output| output.unwrap_or(0));
+        match address
+        {
+            0xFF76 => outputs[1] << 4 | outputs[0],
+            _      => outputs[3] << 4 | outputs[2]
+        }
+    }
+
+    // Low nibble of NR52.
+    fn status(&self) -> u8
+    {
+        (self.ch1.enabled() as u8)
+            | (self.ch2.enabled() as u8) << 1
+            | (self.ch3.enabled() as u8) << 2
+            | (self.ch4.enabled() as u8) << 3
+    }
+
+    // ==========================
+    // Timing
+    // ==========================
+
+    // Advances the channels by a number of T-cycles at normal speed.
+    pub fn tick(&mut self, cycles : u8)
+    {
+        let listening = self.output.is_some() || self.capture.is_some();
+        for offset in 0..cycles
+        {
+            if self.power
+            {
+                self.ch1.tick();
+                self.ch2.tick();
+                self.ch3.tick();
+                self.ch4.tick();
+            }
+
+            if listening
+            {
+                let outputs = self.channel_outputs();
+                if outputs != self.last_outputs || self.dirty
+                {
+                    self.last_outputs = outputs;
+                    self.dirty        = false;
+                    self.level_changed(offset as u32);
+                }
+            }
+        }
+
+        self.cycles += cycles as u64;
+
+        if self.scope.advance(cycles as u32)
+        {
+            self.scope.push(self.channel_levels());
+        }
+
+        if let Some(output) = &mut self.output
+        {
+            let samples = &mut self.samples;
+            output.advance(cycles as u32, |frame| samples.push(frame));
+        }
+        if let Some(capture) = &mut self.capture
+        {
+            let captured = &mut self.captured;
+            capture.advance(cycles as u32, |frame| captured.push(frame));
+        }
+    }
+
+    // Hands the current levels to the outputs, `offset` T-cycles into this tick.
+    fn level_changed(&mut self, offset : u32)
+    {
+        let levels        = self.channel_levels();
+        let (left, right) = self.mix(levels);
+        if let Some(output) = &mut self.output
+        {
+            output.set(offset, [left, right]);
+        }
+        if let Some(capture) = &mut self.capture
+        {
+            let stems = levels.map(|level| level * STEM_GAIN);
+            capture.set(offset, [left, right, stems[0], stems[1], stems[2], stems[3]]);
+        }
+    }
+
+    // Called on the falling edge of DIV bit 4 (bit 5 in double speed), 512 Hz.
+    //
+    // Step   : 0 1 2 3 4 5 6 7
+    // Length : x   x   x   x
+    // Sweep  :     x       x
+    // Volume :               x
+    pub fn clock_frame_sequencer(&mut self)
+    {
+        if !self.power
+        {
+            return;
+        }
+
+        let step = self.frame_step;
+        if step & 1 == 0
+        {
+            self.ch1.clock_length();
+            self.ch2.clock_length();
+            self.ch3.clock_length();
+            self.ch4.clock_length();
+        }
+        if step == 2 || step == 6
+        {
+            self.ch1.clock_sweep();
+        }
+        if step == 7
+        {
+            self.ch1.envelope.clock();
+            self.ch2.envelope.clock();
+            self.ch4.envelope.clock();
+        }
+        self.frame_step = (step + 1) & 0x07;
+    }
+
+    // ==========================
+    // Host output
+    // ==========================
+
+    // Starts synthesizing at `rate` Hz. Changing the rate while running keeps
+    // the queued frames, so frontends can nudge it to steer the fill level.
+    pub fn set_sample_rate(&mut self, rate : f64)
+    {
+        match &mut self.output
+        {
+            Some(output) => output.set_rate(rate),
+            None =>
+            {
+                self.output  = Some(Blip::new(rate, self.cgb));
+                self.samples = SampleBuffer::new((rate * BUFFER_SECONDS) as usize);
+                self.dirty   = true;
+            }
+        }
+    }
+
+    pub fn sample_rate(&self) -> Option<f64>
+    {
+        self.output.as_ref().map(|output| output.rate())
+    }
+
+    pub fn disable_output(&mut self)
+    {
+        self.output = None;
+        self.samples.clear();
+    }
+
+    pub fn samples(&self) -> &SampleBuffer
+    {
+        &self.samples
+    }
+
+    pub fn samples_mut(&mut self) -> &mut SampleBuffer
+    {
+        &mut self.samples
+    }
+
+    // Starts collecting mix and channel frames at `rate` Hz, drained with
+    // take_captured().
+    pub fn start_capture(&mut self, rate : u32)
+    {
+        self.capture = Some(Blip::new(rate as f64, self.cgb));
+        self.captured.clear();
+        self.dirty   = true;
+    }
+
+    pub fn capture_rate(&self) -> Option<f64>
+    {
+        self.capture.as_ref().map(|capture| capture.rate())
+    }
+
+    pub fn stop_capture(&mut self)
+    {
+        self.capture = None;
+    }
+
+    pub fn take_captured(&mut self) -> Vec<[f32; CAPTURE_CHANNELS]>
+    {
+        std::mem::take(&mut self.captured)
+    }
+
+    // ==========================
+    // Register log
+    // ==========================
+
+    pub fn cycles(&self) -> u64 { self.cycles }
+
+    // Starts logging register writes, drained with take_log().
+    pub fn start_log(&mut self)
+    {
+        self.log = Some(Vec::new());
+    }
+
+    pub fn stop_log(&mut self)
+    {
+        self.log = None;
+    }
+
+    pub fn take_log(&mut self) -> Vec<RegWrite>
+    {
+        self.log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // Writes that recreate the current state in a fresh APU, without
+    // retriggering any channel.
+    pub fn state_writes(&self) -> Vec<(u16, u8)>
+    {
+        let mut writes = vec![(0xFF26, (self.power as u8) << 7)];
+        if !self.power
+        {
+            return writes;
+        }
+
+        writes.push((0xFF24, self.regs[NR50]));
+        writes.push((0xFF25, self.regs[NR51]));
+
+        // Wave RAM is only freely writable with CH3 off.
+        writes.push((0xFF1A, 0x00));
+        for (i, byte) in self.ch3.ram.iter().enumerate()
+        {
+            writes.push((0xFF30 + i as u16, *byte));
+        }
+
+        for index in 0x00..0x14
+        {
+            let value = if index % 5 == 4 { self.regs[index] & 0x7F } else { self.regs[index] };
+            writes.push((0xFF10 + index as u16, value));
+        }
+        writes
+    }
+
+    // ==========================
+    // Mixing
+    // ==========================
+
+    // Digital channel outputs 0-15, None while the channel's DAC is off.
+    pub fn channel_outputs(&self) -> [Option<u8>; 4]
+    {
+        [
+            if self.ch1.dac() { Some(self.ch1.output()) } else { None },
+            if self.ch2.dac() { Some(self.ch2.output()) } else { None },
+            if self.ch3.dac() { Some(self.ch3.output()) } else { None },
+            if self.ch4.dac() { Some(self.ch4.output()) } else { None }
+        ]
+    }
+
+    // DAC outputs in -1.0..=1.0, 0.0 for DACs that are off.
+    pub fn channel_levels(&self) -> [f32; 4]
+    {
+        if !self.power
+        {
+            return [0.0; 4];
+        }
+
+        // The DACs map 0-15 onto a falling voltage.
+        self.channel_outputs().map(|output| match output
+        {
+            Some(digital) => 1.0 - digital as f32 / 7.5,
+            None          => 0.0
+        })
+    }
+
+    // Current left and right levels in -1.0..=1.0, before any filtering.
+    pub fn output(&self) -> (f32, f32)
+    {
+        self.mix(self.channel_levels())
+    }
+
+    fn mix(&self, levels : [f32; 4]) -> (f32, f32)
+    {
+        let panning   = self.regs[NR51];
+        let mut left  = 0.0;
+        let mut right = 0.0;
+        for (i, analog) in levels.iter().enumerate()
+        {
+            if !self.audible(Channel::ALL[i])
+            {
+                continue;
+            }
+            if panning & (0x10 << i) != 0
+            {
+                left += analog;
+            }
+            if panning & (0x01 << i) != 0
+            {
+                right += analog;
+            }
+        }
+
+        let volume = self.regs[NR50];
+        let left_volume  = (((volume >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((volume & 0x07) + 1) as f32 / 8.0;
+        (left * left_volume / 4.0, right * right_volume / 4.0)
+    }
+
+    // ==========================
+    // Channel controls
+    // ==========================
+
+    pub fn set_muted(&mut self, channel : Channel, muted : bool)
+    {
+        self.muted[channel as usize] = muted;
+        self.dirty = true;
+    }
+
+    pub fn muted(&self, channel : Channel) -> bool
+    {
+        self.muted[channel as usize]
+    }
+
+    // While any channel is soloed, only soloed channels are mixed.
+    pub fn set_solo(&mut self, channel : Channel, solo : bool)
+    {
+        self.solo[channel as usize] = solo;
+        self.dirty = true;
+    }
+
+    pub fn solo(&self, channel : Channel) -> bool
+    {
+        self.solo[channel as usize]
+    }
+
+    pub fn audible(&self, channel : Channel) -> bool
+    {
+        let index = channel as usize;
+        if self.solo.contains(&true)
+        {
+            return self.solo[index] && !self.muted[index];
+        }
+        !self.muted[index]
+    }
+
+    pub fn voices(&self) -> [Voice; 4]
+    {
+        let frequencies = self.frequencies();
+        let volumes     = self.volumes();
+        let active      = [self.ch1.enabled() && self.ch1.dac(),
+                           self.ch2.enabled() && self.ch2.dac(),
+                           self.ch3.enabled() && self.ch3.dac(),
+                           self.ch4.enabled() && self.ch4.dac()];
+
+        Channel::ALL.map(|channel|
+        {
+            let index = channel as usize;
+            Voice
+            {
+                active    : active[index],
+                frequency : frequencies[index],
+                volume    : volumes[index],
+                triggers  : self.triggers[index]
+            }
+        })
+    }
+
+    // Tone frequencies, the LFSR clock for noise.
+    fn frequencies(&self) -> [f32; 4]
+    {
+        let pulse_hz = |freq : u16| CLOCK_HZ as f32 / (32 * (2048 - freq as u32)) as f32;
+        [pulse_hz(self.ch1.freq()), pulse_hz(self.ch2.freq()), pulse_hz(self.ch3.freq()) / 2.0, self.ch4.frequency()]
+    }
+
+    // 0-15, for the wave channel the output level of a full scale sample.
+    fn volumes(&self) -> [u8; 4]
+    {
+        let wave_volume = match self.ch3.volume()
+        {
+            0 => 0,
+            v => 15 >> (v - 1)
+        };
+        [self.ch1.envelope.volume(), self.ch2.envelope.volume(), wave_volume, self.ch4.envelope.volume()]
+    }
+
+    pub fn visualization(&self) -> Visualization
+    {
+        let frequencies = self.frequencies();
+        let volumes     = self.volumes();
+        let state       = |channel : Channel, enabled, dac, duty| ChannelState
+        {
+            channel,
+            enabled,
+            dac,
+            audible   : self.audible(channel),
+            frequency : frequencies[channel as usize],
+            volume    : volumes[channel as usize],
+            duty,
+            scope     : self.scope.channel(channel)
+        };
+
+        Visualization
+        {
+            channels : vec!
+            [
+                state(Channel::PULSE1, self.ch1.enabled(), self.ch1.dac(), Some(self.ch1.duty())),
+                state(Channel::PULSE2, self.ch2.enabled(), self.ch2.dac(), Some(self.ch2.duty())),
+                state(Channel::WAVE,   self.ch3.enabled(), self.ch3.dac(), None),
+                state(Channel::NOISE,  self.ch4.enabled(), self.ch4.dac(), None)
+            ],
+            wave_ram : self.ch3.ram
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn frame_sequencer_steps()
+    {
+        let mut apu = APU::new();
+        apu.write_reg(0xFF26, 0x00);
+        apu.write_reg(0xFF26, 0x80);
+
+        // CH1 sweeps up by 1/128, CH2 decays, CH4 has four length steps.
+        for (address, value) in [(0xFF10, 0x17), (0xFF12, 0xF0), (0xFF13, 0x00), (0xFF14, 0x84),
+                                 (0xFF17, 0xF1), (0xFF19, 0x80),
+                                 (0xFF20, 0x3C), (0xFF21, 0xF0), (0xFF23, 0xC0)]
+        {
+            apu.write_reg(address, value);
+        }
+
+        let mut steps = Vec::new();
+        for _ in 0..8
+        {
+            apu.clock_frame_sequencer();
+            steps.push((apu.ch1.freq(), apu.ch2.envelope.volume(), apu.read_reg(0xFF26) & 0x08 != 0));
+        }
+        assert_eq!(steps, vec![(0x400, 15, true), (0x400, 15, true), (0x408, 15, true), (0x408, 15, true),
+                               (0x408, 15, true), (0x408, 15, true), (0x410, 15, false), (0x410, 14, false)]);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::apu::units::dac_enabled;
+use crate::apu::units::Envelope;
+use crate::apu::units::Length;
+use crate::timer::CLOCK_HZ;
+
+const DIVISORS : [u16; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// CH4, a 15 or 7 bit LFSR.
+pub struct Noise
+{
+    enabled : bool,
+    dac     : bool,
+    shift   : u8,
+    narrow  : bool,
+    divisor : u8,
+    timer   : u32,
+    lfsr    : u16,
+
+    pub length   : Length,
+    pub envelope : Envelope
+}
+
+impl Noise
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        Noise
+        {
+            enabled : false,
+            dac     : false,
+            shift   : 0,
+            narrow  : false,
+            divisor : 0,
+            timer   : DIVISORS[0] as u32,
+            lfsr    : 0,
+
+            length   : Length::new(64),
+            envelope : Envelope::new()
+        }
+    }
+
+    pub fn enabled(&self) -> bool { self.enabled }
+    pub fn dac(&self) -> bool { self.dac }
+    pub fn narrow(&self) -> bool { self.narrow }
+
+    // LFSR clock rate in Hz.
+    pub fn frequency(&self) -> f32
+    {
+        CLOCK_HZ as f32 / self.period() as f32
+    }
+
+    pub fn power_off(&mut self)
+    {
+        let length = std::mem::replace(&mut self.length, Length::new(64));
+        *self = Noise::new();
+        self.length = length;
+        self.length.enabled = false;
+    }
+
+    fn period(&self) -> u32
+    {
+        (DIVISORS[self.divisor as usize] as u32) << self.shift
+    }
+
+    pub fn write(&mut self, reg : u8, value : u8, extra : bool)
+    {
+        match reg
+        {
+            1 => self.length.load((value & 0x3F) as u16),
+            2 =>
+            {
+                self.envelope.write(value);
+                self.dac = dac_enabled(value);
+                if !self.dac
+                {
+                    self.enabled = false;
+                }
+            },
+            3 =>
+            {
+                self.shift   = value >> 4;
+                self.narrow  = value & 0x08 != 0;
+                self.divisor = value & 0x07;
+            },
+            4 =>
+            {
+                let trigger = value & 0x80 != 0;
+                if self.length.write_control(value & 0x40 != 0, trigger, extra)
+                {
+                    self.enabled = false;
+                }
+                if trigger
+                {
+                    self.enabled = self.dac;
+                    self.lfsr    = 0x7FFF;
+                    self.timer   = self.period();
+                    self.envelope.trigger();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self)
+    {
+        self.timer -= 1;
+        if self.timer > 0
+        {
+            return;
+        }
+        self.timer = self.period();
+
+        // Shifts 14 and 15 stop the LFSR.
+        if self.shift >= 14
+        {
+            return;
+        }
+
+        let xor   = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (xor << 14);
+        if self.narrow
+        {
+            self.lfsr = (self.lfsr & !0x40) | (xor << 6);
+        }
+    }
+
+    pub fn clock_length(&mut self)
+    {
+        if self.length.clock()
+        {
+            self.enabled = false;
+        }
+    }
+
+    pub fn output(&self) -> u8
+    {
+        if !self.enabled || self.lfsr & 1 != 0
+        {
+            return 0;
+        }
+        self.envelope.volume()
+    }
+}
@@ -0,0 +1,289 @@
+use crate::apu::units::dac_enabled;
+use crate::apu::units::Envelope;
+use crate::apu::units::Length;
+
+const DUTY : [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+// CH1 frequency sweep.
+struct Sweep
+{
+    period   : u8,
+    negate   : bool,
+    shift    : u8,
+    timer    : u8,
+    shadow   : u16,
+    enabled  : bool,
+    // A negate calculation happened since the last trigger. Clearing
+    // negate afterwards disables the channel.
+    negated  : bool
+}
+
+impl Sweep
+{
+    fn new() -> Self
+    {
+        Sweep
+        {
+            period  : 0,
+            negate  : false,
+            shift   : 0,
+            timer   : 0,
+            shadow  : 0,
+            enabled : false,
+            negated : false
+        }
+    }
+
+    fn reload_timer(&mut self)
+    {
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    fn calculate(&mut self) -> u16
+    {
+        let delta = self.shadow >> self.shift;
+        if self.negate
+        {
+            self.negated = true;
+            self.shadow - delta
+        }
+        else
+        {
+            self.shadow + delta
+        }
+    }
+}
+
+// CH1 (with sweep) and CH2.
+pub struct Pulse
+{
+    sweep    : Option<Sweep>,
+    enabled  : bool,
+    dac      : bool,
+    duty     : u8,
+    duty_pos : u8,
+    freq     : u16,
+    timer    : u16,
+
+    pub length   : Length,
+    pub envelope : Envelope
+}
+
+impl Pulse
+{
+    pub fn new(sweep : bool) -> Self
+    {
+        Pulse
+        {
+            sweep    : if sweep { Some(Sweep::new()) } else { None },
+            enabled  : false,
+            dac      : false,
+            duty     : 0,
+            duty_pos : 0,
+            freq     : 0,
+            timer    : 2048 * 4,
+
+            length   : Length::new(64),
+            envelope : Envelope::new()
+        }
+    }
+
+    pub fn enabled(&self) -> bool { self.enabled }
+    pub fn dac(&self) -> bool { self.dac }
+    pub fn freq(&self) -> u16 { self.freq }
+    pub fn duty(&self) -> u8 { self.duty }
+
+    // Power off clears everything but the length counter, which the caller
+    // handles per model.
+    pub fn power_off(&mut self)
+    {
+        let length = std::mem::replace(&mut self.length, Length::new(64));
+        *self = Pulse::new(self.sweep.is_some());
+        self.length = length;
+        self.length.enabled = false;
+    }
+
+    // Register 0-4 of this channel's block (NRx0-NRx4).
+    pub fn write(&mut self, reg : u8, value : u8, extra : bool)
+    {
+        match reg
+        {
+            0 =>
+            {
+                if let Some(sweep) = &mut self.sweep
+                {
+                    let was_negate = sweep.negate;
+                    sweep.period   = (value >> 4) & 0x07;
+                    sweep.negate   = value & 0x08 != 0;
+                    sweep.shift    = value & 0x07;
+                    if was_negate && !sweep.negate && sweep.negated
+                    {
+                        self.enabled = false;
+                    }
+                }
+            },
+            1 =>
+            {
+                self.duty = value >> 6;
+                self.length.load((value & 0x3F) as u16);
+            },
+            2 =>
+            {
+                self.envelope.write(value);
+                self.dac = dac_enabled(value);
+                if !self.dac
+                {
+                    self.enabled = false;
+                }
+            },
+            3 => self.freq = (self.freq & 0x700) | value as u16,
+            _ =>
+            {
+                self.freq    = (self.freq & 0xFF) | (((value & 0x07) as u16) << 8);
+                let trigger  = value & 0x80 != 0;
+                if self.length.write_control(value & 0x40 != 0, trigger, extra)
+                {
+                    self.enabled = false;
+                }
+                if trigger
+                {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self)
+    {
+        self.enabled = self.dac;
+        self.timer   = (2048 - self.freq) * 4;
+        self.envelope.trigger();
+
+        if let Some(sweep) = &mut self.sweep
+        {
+            sweep.shadow  = self.freq;
+            sweep.negated = false;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            if sweep.shift != 0 && sweep.calculate() > 2047
+            {
+                self.enabled = false;
+            }
+        }
+    }
+
+    // One T-cycle.
+    pub fn tick(&mut self)
+    {
+        self.timer -= 1;
+        if self.timer == 0
+        {
+            self.timer    = (2048 - self.freq) * 4;
+            self.duty_pos = (self.duty_pos + 1) & 0x07;
+        }
+    }
+
+    // 128 Hz clock.
+    pub fn clock_sweep(&mut self)
+    {
+        let Some(sweep) = &mut self.sweep else { return; };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0
+        {
+            return;
+        }
+        sweep.reload_timer();
+
+        if !sweep.enabled || sweep.period == 0
+        {
+            return;
+        }
+
+        let freq = sweep.calculate();
+        if freq > 2047
+        {
+            self.enabled = false;
+        }
+        else if sweep.shift != 0
+        {
+            sweep.shadow = freq;
+            self.freq    = freq;
+            if sweep.calculate() > 2047
+            {
+                self.enabled = false;
+            }
+        }
+    }
+
+    pub fn clock_length(&mut self)
+    {
+        if self.length.clock()
+        {
+            self.enabled = false;
+        }
+    }
+
+    // Digital output 0-15.
+    pub fn output(&self) -> u8
+    {
+        if !self.enabled
+        {
+            return 0;
+        }
+        let high = (DUTY[self.duty as usize] >> (7 - self.duty_pos)) & 1;
+        high * self.envelope.volume()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // CH1 with the DAC on, the given NR10 and frequency, triggered.
+    fn ch1(nr10 : u8, freq : u16) -> Pulse
+    {
+        let mut pulse = Pulse::new(true);
+        pulse.write(2, 0xF0, false);
+        pulse.write(0, nr10, false);
+        pulse.write(3, freq as u8, false);
+        pulse.write(4, 0x80 | (freq >> 8) as u8, false);
+        pulse
+    }
+
+    #[test]
+    fn sweep_overflow()
+    {
+        // The calculation on trigger already overflows.
+        assert!(!ch1(0x11, 0x7FF).enabled());
+
+        // 0x500 + 0x280 fits, the follow-up calculation does not.
+        let mut pulse = ch1(0x11, 0x500);
+        assert!(pulse.enabled());
+        pulse.clock_sweep();
+        assert_eq!(pulse.freq(), 0x780);
+        assert!(!pulse.enabled());
+
+        // Shift 0 still checks for overflow but keeps the frequency.
+        let mut pulse = ch1(0x10, 0x400);
+        pulse.clock_sweep();
+        assert_eq!(pulse.freq(), 0x400);
+        assert!(!pulse.enabled());
+    }
+
+    #[test]
+    fn sweep_timer_and_negate()
+    {
+        let mut pulse = ch1(0x29, 0x400);
+        pulse.clock_sweep();
+        assert_eq!(pulse.freq(), 0x400);
+        pulse.clock_sweep();
+        assert_eq!(pulse.freq(), 0x200);
+        assert!(pulse.enabled());
+
+        // Leaving negate mode after a negated calculation kills the channel.
+        pulse.write(0, 0x21, false);
+        assert!(!pulse.enabled());
+    }
+}
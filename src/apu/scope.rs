@@ -0,0 +1,156 @@
+use std::fmt::Write as _;
+
+// Samples kept per channel, one every SCOPE_INTERVAL T-cycles: 512 at
+// 32 kHz is a little under one frame.
+pub const SCOPE_LENGTH   : usize = 512;
+pub const SCOPE_INTERVAL : u32   = 128;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Channel
+{
+    PULSE1,
+    PULSE2,
+    WAVE,
+    NOISE
+}
+
+impl Channel
+{
+    pub const ALL : [Channel; 4] = [Channel::PULSE1, Channel::PULSE2, Channel::WAVE, Channel::NOISE];
+
+    // 1-4 as on the CLI.
+    pub fn from_number(number : usize) -> Option<Self>
+    {
+        Channel::ALL.get(number.checked_sub(1)?).copied()
+    }
+
+    pub fn number(&self) -> usize
+    {
+        *self as usize + 1
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Channel::PULSE1 => "CH1 Pulse",
+            Channel::PULSE2 => "CH2 Pulse",
+            Channel::WAVE   => "CH3 Wave",
+            Channel::NOISE  => "CH4 Noise"
+        }
+    }
+}
+
+// Ring of recent DAC levels per channel.
+pub struct Scope
+{
+    samples : [[f32; SCOPE_LENGTH]; 4],
+    head    : usize,
+    cycles  : u32
+}
+
+impl Scope
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        Scope
+        {
+            samples : [[0.0; SCOPE_LENGTH]; 4],
+            head    : 0,
+            cycles  : 0
+        }
+    }
+
+    // Whether a sample is due after `cycles` more T-cycles.
+    pub fn advance(&mut self, cycles : u32) -> bool
+    {
+        self.cycles += cycles;
+        if self.cycles < SCOPE_INTERVAL
+        {
+            return false;
+        }
+        self.cycles -= SCOPE_INTERVAL;
+        true
+    }
+
+    pub fn push(&mut self, levels : [f32; 4])
+    {
+        for (samples, level) in self.samples.iter_mut().zip(levels)
+        {
+            samples[self.head] = level;
+        }
+        self.head = (self.head + 1) % SCOPE_LENGTH;
+    }
+
+    // Oldest sample first.
+    pub fn channel(&self, channel : Channel) -> Vec<f32>
+    {
+        let samples = &self.samples[channel as usize];
+        samples[self.head..].iter().chain(&samples[..self.head]).copied().collect()
+    }
+}
+
+pub struct ChannelState
+{
+    pub channel   : Channel,
+    pub enabled   : bool,
+    pub dac       : bool,
+    pub audible   : bool,
+    // Tone frequency, the LFSR clock for noise.
+    pub frequency : f32,
+    // 0-15, for the wave channel the output level of a full scale sample.
+    pub volume    : u8,
+    // Pulse duty 0-3: 12.5%, 25%, 50%, 75%.
+    pub duty      : Option<u8>,
+    pub scope     : Vec<f32>
+}
+
+// What a channel plays right now, cheap enough to poll often.
+#[derive(Copy, Clone)]
+pub struct Voice
+{
+    // Enabled with the DAC on.
+    pub active    : bool,
+    pub frequency : f32,
+    pub volume    : u8,
+    // Counts triggers, so retriggers at the same pitch show up.
+    pub triggers  : u32
+}
+
+// Snapshot for frontends, taken once per frame or whenever needed.
+pub struct Visualization
+{
+    pub channels : Vec<ChannelState>,
+    pub wave_ram : [u8; 0x10]
+}
+
+impl Visualization
+{
+    // Plain text for debug dumps.
+    pub fn listing(&self) -> String
+    {
+        let mut out = String::new();
+        for state in &self.channels
+        {
+            let peak = state.scope.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            writeln!(out, "{:<10} {:<3} dac {:<3} {:<6} {:>9.2} Hz  vol {:>2}  duty {}  peak {:.2}",
+                     state.channel.name(),
+                     if state.enabled { "on" } else { "off" },
+                     if state.dac { "on" } else { "off" },
+                     if state.audible { "" } else { "muted" },
+                     state.frequency,
+                     state.volume,
+                     state.duty.map(|d| d.to_string()).unwrap_or("-".to_string()),
+                     peak).ok();
+        }
+
+        out.push_str("Wave RAM  ");
+        for byte in self.wave_ram
+        {
+            write!(out, " {:02X}", byte).ok();
+        }
+        out.push('\n');
+        out
+    }
+}
@@ -0,0 +1,203 @@
+// Length counter and volume envelope, shared by the channels.
+
+pub struct Length
+{
+    counter     : u16,
+    max         : u16,
+    pub enabled : bool
+}
+
+impl Length
+{
+    pub fn new(max : u16) -> Self
+    {
+        Length
+        {
+            counter : 0,
+            max,
+            enabled : false
+        }
+    }
+
+    pub fn counter(&self) -> u16 { self.counter }
+
+    // NRx1 length data, counts up from here towards max.
+    pub fn load(&mut self, value : u16)
+    {
+        self.counter = self.max - value;
+    }
+
+    pub fn reset(&mut self)
+    {
+        self.counter = 0;
+        self.enabled = false;
+    }
+
+    // 256 Hz clock, returns true when the channel runs out.
+    pub fn clock(&mut self) -> bool
+    {
+        if self.enabled && self.counter > 0
+        {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    // NRx4 write. `extra` is set when the frame sequencer's next step does
+    // not clock lengths, in which case enabling the counter clocks it once
+    // right away. Returns true if that disables the channel.
+    pub fn write_control(&mut self, enable : bool, trigger : bool, extra : bool) -> bool
+    {
+        let was_enabled = self.enabled;
+        self.enabled    = enable;
+
+        let mut disable = false;
+        if extra && !was_enabled && enable && self.counter > 0
+        {
+            self.counter -= 1;
+            disable = self.counter == 0 && !trigger;
+        }
+
+        if trigger && self.counter == 0
+        {
+            self.counter = self.max;
+            if enable && extra
+            {
+                self.counter -= 1;
+            }
+        }
+        disable
+    }
+}
+
+pub struct Envelope
+{
+    initial  : u8,
+    increase : bool,
+    period   : u8,
+    volume   : u8,
+    timer    : u8
+}
+
+impl Envelope
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        Envelope
+        {
+            initial  : 0,
+            increase : false,
+            period   : 0,
+            volume   : 0,
+            timer    : 0
+        }
+    }
+
+    pub fn volume(&self) -> u8 { self.volume }
+
+    // NRx2, the upper five bits double as the DAC enable.
+    pub fn write(&mut self, value : u8)
+    {
+        self.initial  = value >> 4;
+        self.increase = value & 0x08 != 0;
+        self.period   = value & 0x07;
+    }
+
+    pub fn trigger(&mut self)
+    {
+        self.volume = self.initial;
+        self.timer  = if self.period == 0 { 8 } else { self.period };
+    }
+
+    // 64 Hz clock.
+    pub fn clock(&mut self)
+    {
+        if self.period == 0
+        {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0
+        {
+            self.timer = self.period;
+            if self.increase && self.volume < 15
+            {
+                self.volume += 1;
+            }
+            else if !self.increase && self.volume > 0
+            {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+pub fn dac_enabled(nrx2 : u8) -> bool
+{
+    nrx2 & 0xF8 != 0
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn length_extra_clock()
+    {
+        // Enabling in the first half of a length period clocks once more.
+        let mut length = Length::new(64);
+        length.load(62);
+        assert!(!length.write_control(true, false, true));
+        assert_eq!(length.counter(), 1);
+        assert!(length.clock());
+
+        let mut length = Length::new(64);
+        length.load(63);
+        assert!(length.write_control(true, false, true));
+
+        // Without the extra clock, or already enabled, nothing happens.
+        let mut length = Length::new(64);
+        length.load(63);
+        assert!(!length.write_control(true, false, false));
+        assert!(!length.write_control(true, false, true));
+        assert_eq!(length.counter(), 1);
+
+        // A trigger reloading a finished counter takes the extra clock too,
+        // and the trigger keeps the channel on.
+        let mut length = Length::new(64);
+        length.load(63);
+        length.write_control(true, false, false);
+        assert!(length.clock());
+        length.enabled = false;
+        assert!(!length.write_control(true, true, true));
+        assert_eq!(length.counter(), 63);
+    }
+
+    #[test]
+    fn envelope_steps()
+    {
+        let mut envelope = Envelope::new();
+        envelope.write(0x22);
+        envelope.trigger();
+        let volumes : Vec<u8> = (0..4).map(|_| { envelope.clock(); envelope.volume() }).collect();
+        assert_eq!(volumes, vec![2, 1, 1, 0]);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.volume(), 0);
+
+        envelope.write(0xE9);
+        envelope.trigger();
+        (0..3).for_each(|_| envelope.clock());
+        assert_eq!(envelope.volume(), 15);
+
+        // Period 0 never steps.
+        envelope.write(0x80);
+        envelope.trigger();
+        (0..16).for_each(|_| envelope.clock());
+        assert_eq!(envelope.volume(), 8);
+    }
+}
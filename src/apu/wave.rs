@@ -0,0 +1,186 @@
+use crate::apu::units::Length;
+
+// CH3, plays 32 4-bit samples from wave RAM.
+pub struct Wave
+{
+    enabled  : bool,
+    dac      : bool,
+    volume   : u8,
+    freq     : u16,
+    timer    : u16,
+    position : u8,
+    sample   : u8,
+    // T-cycles since the last sample fetch from wave RAM.
+    since_fetch : u8,
+
+    pub ram    : [u8; 0x10],
+    pub length : Length
+}
+
+impl Wave
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        Wave
+        {
+            enabled  : false,
+            dac      : false,
+            volume   : 0,
+            freq     : 0,
+            timer    : 0,
+            position : 0,
+            sample   : 0,
+            since_fetch : 0xFF,
+
+            ram      : [0; 0x10],
+            length   : Length::new(256)
+        }
+    }
+
+    pub fn enabled(&self) -> bool { self.enabled }
+    pub fn dac(&self) -> bool { self.dac }
+    pub fn freq(&self) -> u16 { self.freq }
+    pub fn volume(&self) -> u8 { self.volume }
+    pub fn position(&self) -> u8 { self.position }
+
+    // Wave RAM and the length counter survive.
+    pub fn power_off(&mut self)
+    {
+        let ram    = self.ram;
+        let length = std::mem::replace(&mut self.length, Length::new(256));
+        *self = Wave::new();
+        self.ram    = ram;
+        self.length = length;
+        self.length.enabled = false;
+    }
+
+    pub fn write(&mut self, reg : u8, value : u8, extra : bool)
+    {
+        match reg
+        {
+            0 =>
+            {
+                self.dac = value & 0x80 != 0;
+                if !self.dac
+                {
+                    self.enabled = false;
+                }
+            },
+            1 => self.length.load(value as u16),
+            2 => self.volume = (value >> 5) & 0x03,
+            3 => self.freq   = (self.freq & 0x700) | value as u16,
+            _ =>
+            {
+                self.freq    = (self.freq & 0xFF) | (((value & 0x07) as u16) << 8);
+                let trigger  = value & 0x80 != 0;
+                if self.length.write_control(value & 0x40 != 0, trigger, extra)
+                {
+                    self.enabled = false;
+                }
+                if trigger
+                {
+                    // The sample buffer keeps its old value until the first
+                    // step, which comes slightly late.
+                    self.enabled  = self.dac;
+                    self.position = 0;
+                    self.timer    = (2048 - self.freq) * 2 + 6;
+                }
+            }
+        }
+    }
+
+    // Wave RAM as the CPU sees it. While playing, accesses go to the byte
+    // being played instead of the addressed one. The DMG only lets them
+    // through within an M-cycle of the channel fetching that byte, reads
+    // are 0xFF and writes are lost otherwise.
+    fn ram_index(&self, index : usize, cgb : bool) -> Option<usize>
+    {
+        if !self.enabled
+        {
+            return Some(index);
+        }
+        if cgb || self.since_fetch < 4
+        {
+            return Some((self.position / 2) as usize);
+        }
+        None
+    }
+
+    pub fn read_ram(&self, index : usize, cgb : bool) -> u8
+    {
+        self.ram_index(index, cgb).map(|i| self.ram[i]).unwrap_or(0xFF)
+    }
+
+    pub fn write_ram(&mut self, index : usize, value : u8, cgb : bool)
+    {
+        if let Some(i) = self.ram_index(index, cgb)
+        {
+            self.ram[i] = value;
+        }
+    }
+
+    // DMG: retriggering while the channel is about to fetch the next byte
+    // overwrites the start of wave RAM. A byte in the first block of four
+    // is copied to byte 0, otherwise its whole block goes to bytes 0-3.
+    pub fn corrupt_on_retrigger(&mut self)
+    {
+        if !self.enabled || self.timer > 2
+        {
+            return;
+        }
+
+        let index = (((self.position + 1) & 0x1F) / 2) as usize;
+        if index < 4
+        {
+            self.ram[0] = self.ram[index];
+        }
+        else
+        {
+            let block = index & !3;
+            self.ram.copy_within(block..block + 4, 0);
+        }
+    }
+
+    fn nibble(&self, position : u8) -> u8
+    {
+        let byte = self.ram[(position / 2) as usize];
+        if position & 1 == 0 { byte >> 4 } else { byte & 0x0F }
+    }
+
+    pub fn tick(&mut self)
+    {
+        if !self.enabled
+        {
+            return;
+        }
+
+        self.since_fetch = self.since_fetch.saturating_add(1);
+        self.timer -= 1;
+        if self.timer == 0
+        {
+            self.timer       = (2048 - self.freq) * 2;
+            self.position    = (self.position + 1) & 0x1F;
+            self.sample      = self.nibble(self.position);
+            self.since_fetch = 0;
+        }
+    }
+
+    pub fn clock_length(&mut self)
+    {
+        if self.length.clock()
+        {
+            self.enabled = false;
+        }
+    }
+
+    // Digital output 0-15. Volume code 0 mutes, 1-3 shift right by 0-2.
+    pub fn output(&self) -> u8
+    {
+        if !self.enabled || self.volume == 0
+        {
+            return 0;
+        }
+        self.sample >> (self.volume - 1)
+    }
+}
@@ -0,0 +1,357 @@
+use std::fs;
+use std::io;
+
+use crate::apu::scope::Channel;
+use crate::apu::scope::Voice;
+use crate::apu::APU;
+use crate::timer::CLOCK_HZ;
+
+// Standard MIDI File, format 1: a tempo track and one track per APU
+// channel. The channels are polled at the frame sequencer's 512 Hz, fast
+// enough for every sweep and envelope step.
+const POLL_CYCLES : u64 = CLOCK_HZ as u64 / 512;
+
+// 120 BPM at 480 ticks per quarter note, 960 ticks per second.
+const DIVISION       : u16 = 480;
+const TEMPO          : u32 = 500_000;
+const TICKS_PER_SEC  : u64 = 960;
+
+// Default pitch bend range of +-2 semitones.
+const BEND_RANGE : f32 = 2.0;
+
+// General MIDI programs for the tone channels, noise goes to the drum kit.
+const PROGRAMS   : [u8; 3] = [80, 80, 81];
+const DRUM_CHANNEL : u8    = 9;
+
+struct Track
+{
+    name     : &'static str,
+    channel  : u8,
+    events   : Vec<u8>,
+    last     : u64,
+
+    note     : Option<u8>,
+    velocity : u8,
+    bend     : u16,
+    volume   : u8,
+    triggers : u32
+}
+
+impl Track
+{
+    fn new(name : &'static str, channel : u8) -> Self
+    {
+        Track
+        {
+            name,
+            channel,
+            events   : Vec::new(),
+            last     : 0,
+
+            note     : None,
+            velocity : 0,
+            bend     : 0x2000,
+            volume   : 0,
+            triggers : 0
+        }
+    }
+
+    fn event(&mut self, tick : u64, bytes : &[u8])
+    {
+        write_vlq(&mut self.events, tick - self.last);
+        self.events.extend_from_slice(bytes);
+        self.last = tick;
+    }
+
+    fn note_off(&mut self, tick : u64)
+    {
+        if let Some(note) = self.note.take()
+        {
+            self.event(tick, &[0x80 | self.channel, note, 0x40]);
+        }
+    }
+
+    fn note_on(&mut self, tick : u64, note : u8, velocity : u8)
+    {
+        self.event(tick, &[0x90 | self.channel, note, velocity]);
+        self.note     = Some(note);
+        self.velocity = velocity;
+    }
+
+    fn pitch_bend(&mut self, tick : u64, bend : u16)
+    {
+        if bend != self.bend
+        {
+            self.event(tick, &[0xE0 | self.channel, (bend & 0x7F) as u8, (bend >> 7) as u8]);
+            self.bend = bend;
+        }
+    }
+
+    // Envelope steps after the note on, relative to its velocity.
+    fn expression(&mut self, tick : u64, volume : u8)
+    {
+        if volume != self.volume && self.note.is_some()
+        {
+            let velocity = (self.velocity as u32).max(1);
+            let value    = (velocity_for(volume) as u32 * 127 / velocity).min(127) as u8;
+            self.event(tick, &[0xB0 | self.channel, 11, value]);
+        }
+        self.volume = volume;
+    }
+
+    // Tone channels: new notes on triggers and big pitch jumps, bends for
+    // anything within the bend range of the sounding note.
+    fn update_tone(&mut self, tick : u64, voice : &Voice)
+    {
+        let triggered = voice.triggers != self.triggers;
+        self.triggers = voice.triggers;
+
+        if !voice.active || voice.volume == 0 || voice.frequency < 8.0
+        {
+            self.note_off(tick);
+            self.volume = voice.volume;
+            return;
+        }
+
+        let pitch = 69.0 + 12.0 * (voice.frequency / 440.0).log2();
+        let jump  = self.note.map(|note| (pitch - note as f32).abs() > BEND_RANGE).unwrap_or(true);
+        if triggered || jump
+        {
+            self.note_off(tick);
+            let note = pitch.round().clamp(0.0, 127.0) as u8;
+            self.event(tick, &[0xB0 | self.channel, 11, 127]);
+            self.pitch_bend(tick, bend_for(pitch - note as f32));
+            self.note_on(tick, note, velocity_for(voice.volume));
+            self.volume = voice.volume;
+            return;
+        }
+
+        if let Some(note) = self.note
+        {
+            self.pitch_bend(tick, bend_for(pitch - note as f32));
+        }
+        self.expression(tick, voice.volume);
+    }
+
+    // Noise: a hit per trigger, the LFSR rate picks the drum.
+    fn update_noise(&mut self, tick : u64, voice : &Voice)
+    {
+        let triggered = voice.triggers != self.triggers;
+        self.triggers = voice.triggers;
+
+        if triggered && voice.active && voice.volume > 0
+        {
+            self.note_off(tick);
+            let drum = match voice.frequency
+            {
+                f if f >= 65536.0 => 42, // Closed hi-hat
+                f if f >= 8192.0  => 38, // Snare
+                _                 => 36  // Kick
+            };
+            self.note_on(tick, drum, velocity_for(voice.volume));
+        }
+        else if !voice.active || voice.volume == 0
+        {
+            self.note_off(tick);
+        }
+    }
+
+    fn chunk(&self) -> Vec<u8>
+    {
+        let mut data = Vec::new();
+        meta(&mut data, 0x03, self.name.as_bytes());
+        if self.channel != DRUM_CHANNEL
+        {
+            data.extend_from_slice(&[0x00, 0xC0 | self.channel, PROGRAMS[self.channel as usize]]);
+        }
+        data.extend_from_slice(&self.events);
+        write_vlq(&mut data, 0);
+        data.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+        data
+    }
+}
+
+pub struct Writer
+{
+    path   : String,
+    start  : u64,
+    next   : u64,
+    tracks : Vec<Track>
+}
+
+impl Writer
+{
+    // Created right away so a bad path fails early.
+    pub fn create(path : &str, start : u64) -> io::Result<Self>
+    {
+        fs::File::create(path)?;
+        Ok(Writer
+        {
+            path   : path.to_string(),
+            start,
+            next   : start,
+            tracks : Channel::ALL.iter()
+                                 .zip([0, 1, 2, DRUM_CHANNEL])
+                                 .map(|(channel, midi)| Track::new(channel.name(), midi))
+                                 .collect()
+        })
+    }
+
+    pub fn path(&self) -> &str
+    {
+        &self.path
+    }
+
+    fn tick(&self, cycle : u64) -> u64
+    {
+        (cycle - self.start) * TICKS_PER_SEC / CLOCK_HZ as u64
+    }
+
+    // Call often, does nothing until the next poll is due.
+    pub fn poll(&mut self, apu : &APU)
+    {
+        let cycle = apu.cycles();
+        if cycle < self.next
+        {
+            return;
+        }
+        self.next = cycle + POLL_CYCLES;
+
+        let tick   = self.tick(cycle);
+        let voices = apu.voices();
+        for (track, channel) in self.tracks.iter_mut().zip(Channel::ALL)
+        {
+            let voice = &voices[channel as usize];
+            match channel
+            {
+                Channel::NOISE => track.update_noise(tick, voice),
+                _              => track.update_tone(tick, voice)
+            }
+        }
+    }
+
+    pub fn finish(mut self, cycle : u64) -> io::Result<()>
+    {
+        let tick = self.tick(cycle);
+
+        let mut file = Vec::new();
+        file.extend_from_slice(b"MThd");
+        file.extend_from_slice(&6u32.to_be_bytes());
+        file.extend_from_slice(&1u16.to_be_bytes());
+        file.extend_from_slice(&(self.tracks.len() as u16 + 1).to_be_bytes());
+        file.extend_from_slice(&DIVISION.to_be_bytes());
+
+        let mut tempo = Vec::new();
+        meta(&mut tempo, 0x51, &TEMPO.to_be_bytes()[1..]);
+        write_vlq(&mut tempo, 0);
+        tempo.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+        chunk(&mut file, &tempo);
+
+        for track in &mut self.tracks
+        {
+            track.note_off(tick);
+            chunk(&mut file, &track.chunk());
+        }
+        fs::write(&self.path, file)
+    }
+}
+
+fn velocity_for(volume : u8) -> u8
+{
+    ((volume as u32 * 127 / 15) as u8).max(1)
+}
+
+// Semitones from the note to the 14 bit bend value.
+fn bend_for(semitones : f32) -> u16
+{
+    let bend = 0x2000 as f32 + semitones / BEND_RANGE * 0x1FFF as f32;
+    bend.round().clamp(0.0, 0x3FFF as f32) as u16
+}
+
+fn meta(data : &mut Vec<u8>, kind : u8, payload : &[u8])
+{
+    write_vlq(data, 0);
+    data.extend_from_slice(&[0xFF, kind]);
+    write_vlq(data, payload.len() as u64);
+    data.extend_from_slice(payload);
+}
+
+fn chunk(file : &mut Vec<u8>, data : &[u8])
+{
+    file.extend_from_slice(b"MTrk");
+    file.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    file.extend_from_slice(data);
+}
+
+// Variable length quantity, 7 bits per byte with the high bit on all but
+// the last.
+fn write_vlq(out : &mut Vec<u8>, value : u64)
+{
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut rest  = value >> 7;
+    while rest > 0
+    {
+        bytes.push(0x80 | (rest & 0x7F) as u8);
+        rest >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_support::temp_path;
+
+    #[test]
+    fn header_and_tracks()
+    {
+        let path = temp_path("notes.mid");
+        Writer::create(&path, 0).unwrap().finish(CLOCK_HZ as u64).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(&data[0..8], b"MThd\x00\x00\x00\x06");
+        assert_eq!(&data[8..14], &[0x00, 0x01, 0x00, 0x05, (DIVISION >> 8) as u8, DIVISION as u8]);
+
+        // Tempo track, then one per channel, each with its end of track.
+        let mut offset = 14;
+        let mut tracks = 0;
+        while offset < data.len()
+        {
+            assert_eq!(&data[offset..offset + 4], b"MTrk");
+            let length = u32::from_be_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+            offset += 8 + length;
+            assert_eq!(&data[offset - 3..offset], &[0xFF, 0x2F, 0x00]);
+            tracks += 1;
+        }
+        assert_eq!(offset, data.len());
+        assert_eq!(tracks, 5);
+    }
+
+    #[test]
+    fn tone_events()
+    {
+        let mut track = Track::new(Channel::PULSE1.name(), 0);
+        let a4        = Voice { active : true, frequency : 440.0, volume : 15, triggers : 1 };
+        track.update_tone(0, &a4);
+        track.update_tone(TICKS_PER_SEC, &Voice { volume : 0, ..a4 });
+
+        // Full expression, centered bend (already the default), note on,
+        // then the note off a second later.
+        let mut delay = Vec::new();
+        write_vlq(&mut delay, TICKS_PER_SEC);
+        let expected = [&[0x00, 0xB0, 11, 127, 0x00, 0x90, 69, 127][..], &delay, &[0x80, 69, 0x40]].concat();
+        assert_eq!(track.events, expected);
+    }
+
+    #[test]
+    fn vlq()
+    {
+        let encode = |value : u64| -> Vec<u8> { let mut out = Vec::new(); write_vlq(&mut out, value); out };
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(0x7F), vec![0x7F]);
+        assert_eq!(encode(0x80), vec![0x81, 0x00]);
+        assert_eq!(encode(0x0FFFFFFF), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+}
@@ -0,0 +1,149 @@
+pub mod midi;
+pub mod vgm;
+pub mod wav;
+
+use std::io;
+use std::path::Path;
+
+use crate::apu::buffer::to_i16;
+use crate::apu::scope::Channel;
+use crate::apu::CAPTURE_CHANNELS;
+
+// Writes the stereo mix, and optionally every APU channel as a mono stem
+// next to it: song.wav, song-ch1.wav ... song-ch4.wav. Samples come from
+// the APU's capture tap, so the output depends only on the emulated
+// program and the rate.
+pub struct Recorder
+{
+    path   : String,
+    rate   : u32,
+    frames : u64,
+    mix    : wav::Writer,
+    stems  : Vec<wav::Writer>
+}
+
+impl Recorder
+{
+    pub fn create(path : &str, rate : u32, stems : bool) -> io::Result<Self>
+    {
+        let mix = wav::Writer::create(path, rate, 2)?;
+
+        let mut writers = Vec::new();
+        if stems
+        {
+            for channel in Channel::ALL
+            {
+                writers.push(wav::Writer::create(&stem_path(path, channel), rate, 1)?);
+            }
+        }
+
+        Ok(Recorder
+        {
+            path   : path.to_string(),
+            rate,
+            frames : 0,
+            mix,
+            stems  : writers
+        })
+    }
+
+    pub fn path(&self) -> &str
+    {
+        &self.path
+    }
+
+    pub fn rate(&self) -> u32
+    {
+        self.rate
+    }
+
+    // Frames of left, right and the four channel levels.
+    pub fn push(&mut self, frames : &[[f32; CAPTURE_CHANNELS]]) -> io::Result<()>
+    {
+        for frame in frames
+        {
+            self.mix.push(&[to_i16(frame[0]), to_i16(frame[1])])?;
+            for (stem, level) in self.stems.iter_mut().zip(&frame[2..])
+            {
+                stem.push(&[to_i16(*level)])?;
+            }
+        }
+        self.frames += frames.len() as u64;
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<u64>
+    {
+        self.mix.finish()?;
+        for stem in self.stems
+        {
+            stem.finish()?;
+        }
+        Ok(self.frames)
+    }
+}
+
+// song.wav -> song-ch1.wav
+pub fn stem_path(path : &str, channel : Channel) -> String
+{
+    let path = Path::new(path);
+    let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension()
+    {
+        Some(extension) => format!("{}-ch{}.{}", stem, channel.number(), extension.to_string_lossy()),
+        None            => format!("{}-ch{}", stem, channel.number())
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_support::test_rom;
+    use crate::console::Console;
+
+    // Plays a 50% duty tone on CH2 for a few frames, returns the mix and stems.
+    fn record(rom : &std::path::Path, name : &str) -> Vec<Vec<u8>>
+    {
+        let path = std::env::temp_dir().join(format!("rust_gbc-{}-{}.wav", name, std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+
+        let mut console = Console::new();
+        console.set_trace(false);
+        assert!(console.load(&rom.to_string_lossy()));
+        assert!(console.start_audio_recording(&path, 44100, true));
+        console.run_frames(10);
+        assert!(console.stop_audio_recording());
+
+        let mut files = vec![path.clone()];
+        files.extend(Channel::ALL.map(|channel| stem_path(&path, channel)));
+        files.iter().map(|file|
+        {
+            let data = std::fs::read(file).expect("read wav");
+            std::fs::remove_file(file).ok();
+            data
+        }).collect()
+    }
+
+    #[test]
+    fn recording_is_deterministic()
+    {
+        // LD A,0xF0; LDH (0x17),A; LD A,0x80; LDH (0x16),A; LD A,0x86; LDH (0x19),A; JP 0x015C
+        let rom = test_rom("wav", &[0x3E, 0xF0, 0xE0, 0x17, 0x3E, 0x80, 0xE0, 0x16, 0x3E, 0x86, 0xE0, 0x19, 0xC3, 0x5C, 0x01]);
+        let a   = record(&rom, "wav-a");
+        let b   = record(&rom, "wav-b");
+        std::fs::remove_file(&rom).ok();
+
+        assert_eq!(a, b);
+        assert_eq!(&a[0][0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(a[0][4..8].try_into().unwrap()) as usize, a[0].len() - 8);
+        assert_eq!(u32::from_le_bytes(a[2][40..44].try_into().unwrap()) as usize, a[2].len() - 44);
+
+        // CH2 toggles, CH3 has its DAC off.
+        let samples = |wav : &[u8]| -> Vec<i16> { wav[44..].chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]])).collect() };
+        let ch2     = samples(&a[2]);
+        assert!(ch2.iter().any(|s| *s > 8000) && ch2.iter().any(|s| *s < -8000));
+        assert!(samples(&a[3]).iter().all(|s| *s == 0));
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use std::io;
+
+use crate::apu::RegWrite;
+use crate::timer::CLOCK_HZ;
+
+// VGM 1.61 with the Game Boy DMG chip. Commands are kept in memory and
+// the file is written by finish(), once the length and loop are known.
+pub const VGM_RATE : u64 = 44100;
+
+const VERSION     : u32   = 0x0000_0161;
+const HEADER_SIZE : usize = 0x100;
+
+const CMD_DMG_WRITE : u8 = 0xB3;
+const CMD_WAIT      : u8 = 0x61;
+const CMD_WAIT_60HZ : u8 = 0x62;
+const CMD_WAIT_50HZ : u8 = 0x63;
+const CMD_WAIT_1    : u8 = 0x70;
+const CMD_END       : u8 = 0x66;
+
+pub struct Writer
+{
+    path    : String,
+    // APU cycle the log starts at.
+    start   : u64,
+    data    : Vec<u8>,
+    samples : u64,
+    // Data offset and sample of the loop point.
+    looped  : Option<(usize, u64)>
+}
+
+impl Writer
+{
+    // `state` recreates the APU at `start` before the logged writes. The
+    // file gets created right away so a bad path fails early.
+    pub fn create(path : &str, start : u64, state : &[(u16, u8)]) -> io::Result<Self>
+    {
+        fs::File::create(path)?;
+
+        let mut writer = Writer
+        {
+            path    : path.to_string(),
+            start,
+            data    : Vec::new(),
+            samples : 0,
+            looped  : None
+        };
+        for (address, value) in state
+        {
+            writer.register(*address, *value);
+        }
+        Ok(writer)
+    }
+
+    pub fn path(&self) -> &str
+    {
+        &self.path
+    }
+
+    pub fn push(&mut self, writes : &[RegWrite])
+    {
+        for write in writes
+        {
+            self.wait_until(write.cycle);
+            self.register(write.address, write.value);
+        }
+    }
+
+    pub fn mark_loop(&mut self, cycle : u64)
+    {
+        self.wait_until(cycle);
+        self.looped = Some((self.data.len(), self.samples));
+    }
+
+    fn register(&mut self, address : u16, value : u8)
+    {
+        self.data.extend_from_slice(&[CMD_DMG_WRITE, (address - 0xFF10) as u8, value]);
+    }
+
+    fn wait_until(&mut self, cycle : u64)
+    {
+        let target = cycle.saturating_sub(self.start) * VGM_RATE / CLOCK_HZ as u64;
+        while self.samples < target
+        {
+            let left = target - self.samples;
+            let wait = match left
+            {
+                1..=16 =>
+                {
+                    self.data.push(CMD_WAIT_1 + (left - 1) as u8);
+                    left
+                },
+                735 =>
+                {
+                    self.data.push(CMD_WAIT_60HZ);
+                    left
+                },
+                882 =>
+                {
+                    self.data.push(CMD_WAIT_50HZ);
+                    left
+                },
+                _ =>
+                {
+                    let wait = left.min(0xFFFF);
+                    self.data.push(CMD_WAIT);
+                    self.data.extend_from_slice(&(wait as u16).to_le_bytes());
+                    wait
+                }
+            };
+            self.samples += wait;
+        }
+    }
+
+    // Returns the length in samples at 44.1 kHz.
+    pub fn finish(mut self, cycle : u64) -> io::Result<u64>
+    {
+        self.wait_until(cycle);
+        self.data.push(CMD_END);
+
+        let mut header = vec![0u8; HEADER_SIZE];
+        let mut put    = |offset : usize, value : u32| header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+
+        put(0x00, u32::from_le_bytes(*b"Vgm "));
+        put(0x04, (HEADER_SIZE + self.data.len() - 0x04) as u32);
+        put(0x08, VERSION);
+        put(0x18, self.samples as u32);
+        if let Some((offset, sample)) = self.looped
+        {
+            put(0x1C, (HEADER_SIZE + offset - 0x1C) as u32);
+            put(0x20, (self.samples - sample) as u32);
+        }
+        put(0x34, (HEADER_SIZE - 0x34) as u32);
+        put(0x80, CLOCK_HZ);
+
+        header.extend_from_slice(&self.data);
+        fs::write(&self.path, header)?;
+        Ok(self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_support::temp_path;
+
+    fn read_u32(data : &[u8], offset : usize) -> u32
+    {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn header_fields()
+    {
+        let path       = temp_path("log.vgm");
+        let one_second = CLOCK_HZ as u64;
+        let start      = 1000;
+
+        let mut writer = Writer::create(&path, start, &[(0xFF26, 0x80)]).unwrap();
+        writer.push(&[RegWrite { cycle : start + one_second / 2, address : 0xFF12, value : 0xF0 }]);
+        writer.mark_loop(start + one_second / 2);
+        assert_eq!(writer.finish(start + one_second).unwrap(), VGM_RATE);
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(&data[0..4], b"Vgm ");
+        assert_eq!(read_u32(&data, 0x04) as usize, data.len() - 0x04);
+        assert_eq!(read_u32(&data, 0x08), VERSION);
+        assert_eq!(read_u32(&data, 0x18) as u64, VGM_RATE);
+        assert_eq!(read_u32(&data, 0x20) as u64, VGM_RATE - VGM_RATE / 2);
+        assert_eq!(read_u32(&data, 0x80), CLOCK_HZ);
+
+        let body  = 0x34 + read_u32(&data, 0x34) as usize;
+        let wait  = (VGM_RATE / 2) as u16;
+        let first = [CMD_DMG_WRITE, 0x16, 0x80, CMD_WAIT, wait as u8, (wait >> 8) as u8, CMD_DMG_WRITE, 0x02, 0xF0];
+        assert_eq!(body, HEADER_SIZE);
+        assert_eq!(&data[body..body + first.len()], &first);
+        assert_eq!(0x1C + read_u32(&data, 0x1C) as usize, body + first.len());
+        assert_eq!(data.last(), Some(&CMD_END));
+    }
+}
@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+// 16-bit PCM RIFF WAVE. The chunk sizes are written as zero and patched
+// in by finish().
+pub struct Writer
+{
+    out      : BufWriter<File>,
+    channels : u16,
+    frames   : u32
+}
+
+impl Writer
+{
+    pub fn create(path : &str, rate : u32, channels : u16) -> io::Result<Self>
+    {
+        let mut out     = BufWriter::new(File::create(path)?);
+        let block_align = channels * 2;
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&channels.to_le_bytes())?;
+        out.write_all(&rate.to_le_bytes())?;
+        out.write_all(&(rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Writer
+        {
+            out,
+            channels,
+            frames   : 0
+        })
+    }
+
+    // One sample per channel.
+    pub fn push(&mut self, frame : &[i16]) -> io::Result<()>
+    {
+        debug_assert_eq!(frame.len(), self.channels as usize);
+        for sample in frame
+        {
+            self.out.write_all(&sample.to_le_bytes())?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<u32>
+    {
+        let data = self.frames * self.channels as u32 * 2;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + data).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data.to_le_bytes())?;
+        self.out.flush()?;
+        Ok(self.frames)
+    }
+}
@@ -1,6 +1,8 @@
+#[allow(clippy::module_inception)]
 pub mod bus
 {
     use crate::cart::Cart;
+    use crate::io::IO;
     use crate::mem::Mem;
     use crate::regs::Regs;
     use crate::cpu_enums::RamType;
@@ -23,18 +25,24 @@ pub mod bus
     pub fn read8(cart    : &Cart, 
                  mem     : &Mem,
                  regs    : &Regs,
+                 io      : &IO,
                  address : u16) -> u8
     {
+        // OAM DMA owns the bus, only I/O and HRAM stay reachable
+        if io.dma.blocking() && address < 0xFF00
+        {
+            return dma_conflict(io, address);
+        }
+
         // ROM
         if address < 0x8000
         {
             cart.read8(address)
         }
-        // Unsupported
+        // VRAM
         else if address < 0xA000
         {
-            // todo
-            panic!("Unsupported BUS Read {:04X}", address)
+            io.ppu.read_vram(address)
         }
         // CART
         else if address < 0xC000
@@ -51,8 +59,7 @@ pub mod bus
         }
         else if address < 0xFEA0
         {
-            //todo
-            panic!("Unsupported BUS Read {:04X}", address)
+            io.ppu.read_oam(address)
         }
         else if address < 0xFF00
         {
@@ -60,8 +67,7 @@ pub mod bus
         }
         else if address  < 0xFF80
         {
-            //todo
-            panic!("Unsupported BUS Read {:04X}", address)
+            io.read8(address)
         }
         else if address == 0xFFFF
         {
@@ -69,34 +75,93 @@ pub mod bus
         }
         else
         {
-            //hram todo
-            0
+            mem.read8(&RamType::HRAM, address)
+        }
+    }
+
+    // Reads that collide with a running DMA see the byte it is moving when
+    // both use the same bus, open bus otherwise.
+    fn dma_conflict(io : &IO, address : u16) -> u8
+    {
+        if address >= 0xFE00
+        {
+            return 0xFF;
+        }
+
+        let source_vram = (0x8000..0xA000).contains(&io.dma.source_address());
+        let target_vram = (0x8000..0xA000).contains(&address);
+        if source_vram == target_vram
+        {
+            io.dma.last()
+        }
+        else
+        {
+            0xFF
+        }
+    }
+
+    // DMA source reads bypass the PPU locks. Sources from 0xE000 up mirror
+    // WRAM on DMG and read as 0xFF on CGB.
+    pub fn dma_read8(cart    : &Cart,
+                     mem     : &Mem,
+                     io      : &IO,
+                     address : u16) -> u8
+    {
+        if address < 0x8000
+        {
+            cart.read8(address)
+        }
+        else if address < 0xA000
+        {
+            io.ppu.read_vram_unlocked(address)
+        }
+        else if address < 0xC000
+        {
+            cart.read8(address)
+        }
+        else if address < 0xE000
+        {
+            mem.read8(&RamType::WRAM, address)
+        }
+        else if io.ppu.cgb()
+        {
+            0xFF
+        }
+        else
+        {
+            mem.read8(&RamType::WRAM, address - 0x2000)
         }
     }
     pub fn read16(cart    : &Cart, 
                   mem     : &Mem,
                   regs    : &Regs,
+                  io      : &IO,
                   address : u16) -> u16
     {
-        let low_byte  = read8(cart, mem, regs, address) as u16;
-        let high_byte = read8(cart, mem, regs, address.wrapping_add(1)) as u16;
+        let low_byte  = read8(cart, mem, regs, io, address) as u16;
+        let high_byte = read8(cart, mem, regs, io, address.wrapping_add(1)) as u16;
         (high_byte << 8) | low_byte
     }
 
     pub fn write8(cart    : &mut Cart, 
                   mem     : &mut Mem,
                   regs    : &mut Regs,
+                  io      : &mut IO,
                   address : u16,
                   value   : u8)
     {
+        if io.dma.blocking() && address < 0xFF00
+        {
+            return;
+        }
+
         if address < 0x8000
         {
             cart.write8(address, value);
         }
         else if address < 0xA000
         {
-            // todo
-            println!("Unsupported BUS Write {:04X}", address);
+            io.ppu.write_vram(address, value);
         }
         else if address < 0xC000
         {
@@ -112,8 +177,7 @@ pub mod bus
         }
         else if address < 0xFEA0
         {
-            // todo
-            println!("Unsupported BUS Write {:04X}", address);
+            io.ppu.write_oam(address, value);
         }
         else if address < 0xFF00
         {
@@ -121,8 +185,7 @@ pub mod bus
         }
         else if address < 0xFF80
         {
-            // todo
-            println!("Unsupported BUS Write {:04X}", address);
+            io.write8(address, value);
         }
         else if address == 0xFFFF
         {
@@ -137,36 +200,40 @@ pub mod bus
     pub fn push8(cart    : &mut Cart,
                  mem     : &mut Mem,
                  regs    : &mut Regs,
+                 io      : &mut IO,
                  value   : u8)
     {
         let sp = regs.read(Reg::SP).wrapping_sub(0x1);
-        write8(cart, mem, regs, sp, value);
+        write8(cart, mem, regs, io, sp, value);
         regs.write(Reg::SP, sp);
     }
     pub fn push16(cart  : &mut Cart,
                   mem   : &mut Mem,
                   regs  : &mut Regs,
+                  io    : &mut IO,
                   value : u16)
     {
-        push8(cart, mem, regs, ((value >> 8) & 0xFF) as u8);
-        push8(cart, mem, regs, (value & 0xFF) as u8);
+        push8(cart, mem, regs, io, ((value >> 8) & 0xFF) as u8);
+        push8(cart, mem, regs, io, (value & 0xFF) as u8);
     }
 
     pub fn pop8(cart    : &mut Cart,
                  mem     : &mut Mem,
-                 regs    : &mut Regs) -> u8
+                 regs    : &mut Regs,
+                 io      : &mut IO) -> u8
     {
         let sp = regs.read(Reg::SP).wrapping_add(0x1);
         regs.write(Reg::SP, sp);
-        read8(cart, mem, regs, sp)
+        read8(cart, mem, regs, io, sp)
     }
 
     pub fn pop16(cart    : &mut Cart,
                  mem     : &mut Mem,
-                 regs    : &mut Regs) -> u16
+                 regs    : &mut Regs,
+                 io      : &mut IO) -> u16
     {
-        let lo = pop8(cart, mem, regs) as u16;
-        let hi = pop8(cart, mem, regs) as u16;
+        let lo = pop8(cart, mem, regs, io) as u16;
+        let hi = pop8(cart, mem, regs, io) as u16;
 
         (hi << 0x8) | lo
     }
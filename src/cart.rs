@@ -5,17 +5,28 @@ use std::path::Path;
 pub struct Cart
 {
     rom_size : u32,
-    rom_data : Vec<u8>
+    rom_data : Vec<u8>,
+
+    // Simple bank switching for GBS images: 0x2000-0x3FFF selects the bank
+    // at 0x4000, 0xA000-0xBFFF is plain RAM.
+    banked   : bool,
+    rom_bank : usize,
+    ram      : Vec<u8>
 }
 
 impl Cart
 {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self
     {
         Cart
         {
             rom_size : 0x00000000,
-            rom_data : Vec::new()
+            rom_data : Vec::new(),
+
+            banked   : false,
+            rom_bank : 1,
+            ram      : Vec::new()
         }
     }
 
@@ -32,6 +43,19 @@ impl Cart
 
     pub fn read8(&self, address : u16) -> u8
     {
+        if self.banked
+        {
+            return match address
+            {
+                0x4000..=0x7FFF =>
+                {
+                    let offset = self.rom_bank * 0x4000 + (address - 0x4000) as usize;
+                    self.rom_data.get(offset).copied().unwrap_or(0xFF)
+                },
+                0xA000..=0xBFFF => self.ram[(address - 0xA000) as usize],
+                _               => self.rom_data[address as usize]
+            };
+        }
         self.rom_data[address as usize]
     }
     pub fn read16(&self, address: u16) -> u16
@@ -42,6 +66,16 @@ impl Cart
     }
     pub fn write8(&mut self, address : u16, value : u8)
     {
+        if self.banked
+        {
+            match address
+            {
+                0x2000..=0x3FFF => self.rom_bank = (value as usize).max(1),
+                0xA000..=0xBFFF => self.ram[(address - 0xA000) as usize] = value,
+                _               => {}
+            }
+            return;
+        }
         self.rom_data[address as usize] = value;
     }
     pub fn write16(&mut self, address : u16, value : u16)
@@ -55,7 +89,7 @@ impl Cart
     pub fn load(&mut self, rom_path : &str) -> bool
     {
         let path     = Path::new(rom_path);
-        let mut file = match File::open(&path)
+        let mut file = match File::open(path)
         {
             Ok(file) => 
             {
@@ -83,9 +117,20 @@ impl Cart
         {
             eprintln!("Failed to read file '{}': {}", rom_path, e);
         }
+        self.banked = false;
 
         true
     }
+    // Maps a prepared image, see gbs::Gbs::image.
+    pub fn load_banked(&mut self, image : Vec<u8>)
+    {
+        self.rom_size = image.len() as u32;
+        self.rom_data = image;
+        self.banked   = true;
+        self.rom_bank = 1;
+        self.ram      = vec![0; 0x2000];
+    }
+
     pub fn logo(&self) -> &[u8]
     {
         &self.rom_data[0x104..=0x133]
@@ -96,6 +141,17 @@ impl Cart
         &self.rom_data[0x134..0x143]
     }
 
+    // Sum of 0x134-0x143, used by the CGB boot ROM to colorize DMG games.
+    pub fn title_checksum(&self) -> u8
+    {
+        self.rom_data[0x134..=0x143].iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+    }
+
+    pub fn has_header(&self) -> bool
+    {
+        self.rom_data.len() >= 0x150
+    }
+
     pub fn title_str(&self) -> &str
     {
         let raw = self.title();
@@ -105,11 +161,7 @@ impl Cart
     pub fn cgb(&self) -> bool
     {
         let raw = self.rom_data[0x143];
-        match raw
-        {
-            0x80 | 0xC0 => true,
-            _ => false,
-        }
+        matches!(raw, 0x80 | 0xC0)
     }
 
     pub fn lic_code(&self) -> u8
@@ -117,6 +169,16 @@ impl Cart
         self.rom_data[0x144]
     }
 
+    pub fn new_lic_code(&self) -> [u8; 2]
+    {
+        [self.rom_data[0x144], self.rom_data[0x145]]
+    }
+
+    pub fn old_lic_code(&self) -> u8
+    {
+        self.rom_data[0x14B]
+    }
+
     pub fn lic_code_str(&self) -> &str
     {
         let raw = &self.rom_data[0x144..=0x145];
@@ -0,0 +1,352 @@
+use crate::apu::blip;
+use crate::apu::scope::Channel;
+use crate::image::filter::Filter;
+use crate::image::ghosting::Ghosting;
+use crate::image::DmgOutput;
+use crate::palette;
+use crate::palette::Combo;
+use crate::palette::DmgPalette;
+use crate::ppu::Layers;
+use crate::ppu::Renderer;
+use crate::video::VideoFormat;
+
+pub const USAGE : &str = "\
+Usage:
+    rust_gbc [rom]
+    rust_gbc run <rom> [options]    .gbs files play their music
+    rust_gbc term <rom> [options]   Play in a truecolor terminal
+
+Options are also read from rust_gbc.cfg (or $RUST_GBC_CONFIG), one per line
+without the dashes, e.g. \"filter = hq2x\". Command line options win.
+
+Run options (term uses the output ones):
+    --frames <n>         Frames to emulate before exiting (default 60)
+    --track <n>          GBS song to play (default: the file's first song)
+    --screenshot <path>  Save the last frame, PNG or .ppm/.pgm
+    --scale <n>          Integer scale factor for saved images (default 1)
+    --renderer <name>    PPU renderer: scanline (default) or fifo, slower
+                         but accurate for mid-line effects
+    --filter <name>      Output filter: nearest, scale2x, scale3x, hq2x, lcd
+    --hide <layers>      Hide output layers, comma separated: bg, window, sprites
+    --no-sprite-limit    Draw more than 10 sprites per line
+    --overlay            Outline sprites and the window
+    --ghosting <mode>    LCD ghosting: off, mix, decay or decay:<percent kept>
+    --shades             Write DMG frames as raw 2-bit shades
+    --palette <name>     DMG colors: gray, green, pocket, light, auto
+                         or four colors as #RRGGBB,#RRGGBB,#RRGGBB,#RRGGBB
+    --palette-combo <c>  DMG colors from a CGB boot ROM button combo:
+                         right, left, up, down, optionally +a or +b
+    --color-correction   Mimic the CGB LCD's gamma and color bleeding
+    --dump-vram <dir>    Save tile sheet, tile maps, OAM and palettes after
+                         the run (live state only, no save states yet)
+    --record <path>      Record every frame as .y4m or animated .gif
+    --record-audio       With --record, also write the sound to <path>.wav
+    --wav <path>         Record audio as 16-bit stereo WAV
+    --stems              With --wav, also write each channel to <path>-ch1..4.wav
+    --sample-rate <hz>   Sample rate for --wav and --record-audio (default 48000)
+    --vgm <path>         Log sound register writes as .vgm
+    --vgm-start <frame>  Start the VGM log at this frame (default 0)
+    --vgm-stop <frame>   Stop the VGM log at this frame (default: the last)
+    --vgm-loop <frame>   Mark the VGM loop point at this frame
+    --midi <path>        Convert the channels' notes to a MIDI file
+    --mute <channels>    Leave channels out of the mix, comma separated 1-4
+    --solo <channels>    Mix only these channels
+    --dump-audio <path>  Save channel state and wave RAM as text
+    --trace              Print every executed instruction";
+
+pub enum Command
+{
+    START(String),
+    RUN(RunOptions),
+    TERM(RunOptions)
+}
+
+pub struct RunOptions
+{
+    pub rom        : String,
+    pub frames     : u64,
+    pub track      : Option<u8>,
+    pub screenshot : Option<String>,
+    pub dump_vram  : Option<String>,
+    pub record     : Option<String>,
+    pub wav        : Option<String>,
+    pub stems      : bool,
+    pub record_audio : bool,
+    pub sample_rate : u32,
+    pub vgm        : Option<String>,
+    pub vgm_start  : u64,
+    pub vgm_stop   : Option<u64>,
+    pub vgm_loop   : Option<u64>,
+    pub midi       : Option<String>,
+    pub mute       : Vec<Channel>,
+    pub solo       : Vec<Channel>,
+    pub dump_audio : Option<String>,
+    pub scale      : usize,
+    pub filter     : Filter,
+    pub ghosting   : Ghosting,
+    pub renderer   : Renderer,
+    pub layers     : Layers,
+    pub overlay    : bool,
+    pub dmg_output : DmgOutput,
+    pub palette    : DmgPalette,
+    pub correction : bool,
+    pub trace      : bool
+}
+
+impl RunOptions
+{
+    pub fn new(rom : &str) -> Self
+    {
+        RunOptions
+        {
+            rom        : rom.to_string(),
+            frames     : 60,
+            track      : None,
+            screenshot : None,
+            dump_vram  : None,
+            record     : None,
+            wav        : None,
+            stems      : false,
+            record_audio : false,
+            sample_rate : 48000,
+            vgm        : None,
+            vgm_start  : 0,
+            vgm_stop   : None,
+            vgm_loop   : None,
+            midi       : None,
+            mute       : Vec::new(),
+            solo       : Vec::new(),
+            dump_audio : None,
+            scale      : 1,
+            filter     : Filter::NEAREST,
+            ghosting   : Ghosting::OFF,
+            renderer   : Renderer::SCANLINE,
+            layers     : Layers::new(),
+            overlay    : false,
+            dmg_output : DmgOutput::RGB,
+            palette    : DmgPalette::GRAYSCALE,
+            correction : false,
+            trace      : false
+        }
+    }
+}
+
+pub fn parse(args : &[String], default_rom : &str) -> Result<Command, String>
+{
+    match args.first().map(|s| s.as_str())
+    {
+        None           => Ok(Command::START(default_rom.to_string())),
+        Some("run")    => parse_run(&with_config(&args[1..])?).map(Command::RUN),
+        Some("term")   => parse_run(&with_config(&args[1..])?).map(Command::TERM),
+        Some(rom)      => Ok(Command::START(rom.to_string()))
+    }
+}
+
+// Run options can also come from a config file, rust_gbc.cfg in the working
+// directory or the file named by $RUST_GBC_CONFIG. One option per line
+// without the dashes, "filter hq2x" or "filter = hq2x", lines starting with # are comments.
+// They go in before the command line ones, which win.
+const CONFIG_FILE : &str = "rust_gbc.cfg";
+
+fn with_config(args : &[String]) -> Result<Vec<String>, String>
+{
+    if args.is_empty()
+    {
+        return Ok(Vec::new());
+    }
+
+    let (path, required) = match std::env::var("RUST_GBC_CONFIG")
+    {
+        Ok(path) => (path, true),
+        Err(_)   => (CONFIG_FILE.to_string(), false)
+    };
+
+    let text = match std::fs::read_to_string(&path)
+    {
+        Ok(text) => text,
+        Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("config '{}': {}", path, e))
+    };
+
+    let mut merged = vec![args[0].clone()];
+    merged.extend(parse_config(&text));
+    merged.extend(args.iter().skip(1).cloned());
+    Ok(merged)
+}
+
+fn parse_config(text : &str) -> Vec<String>
+{
+    let mut args = Vec::new();
+    for line in text.lines()
+    {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#')
+        {
+            continue;
+        }
+
+        let (key, value) = match line.split_once(|c : char| c == '=' || c.is_whitespace())
+        {
+            Some((key, value)) => (key.trim(), value.trim().trim_start_matches('=').trim()),
+            None               => (line, "")
+        };
+        args.push(format!("--{}", key));
+        if !value.is_empty()
+        {
+            args.push(value.to_string());
+        }
+    }
+    args
+}
+
+fn parse_run(args : &[String]) -> Result<RunOptions, String>
+{
+    let rom = args.first().ok_or("run: missing ROM path")?;
+    let mut options = RunOptions::new(rom);
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next()
+    {
+        match arg.as_str()
+        {
+            "--frames"     => options.frames     = parse_number(arg, iter.next())?,
+            "--scale"      => options.scale      = parse_number(arg, iter.next())?,
+            "--track"      => options.track      = Some(parse_number(arg, iter.next())?),
+            "--filter"     =>
+            {
+                let name = value(arg, iter.next())?;
+                options.filter = Filter::from_name(name).ok_or(format!("--filter: unknown filter '{}'", name))?;
+            },
+            "--ghosting"   =>
+            {
+                let name = value(arg, iter.next())?;
+                options.ghosting = Ghosting::from_name(name).ok_or(format!("--ghosting: unknown mode '{}'", name))?;
+            },
+            "--renderer"   =>
+            {
+                let name = value(arg, iter.next())?;
+                options.renderer = Renderer::from_name(name).ok_or(format!("--renderer: unknown renderer '{}'", name))?;
+            },
+            "--screenshot" => options.screenshot = Some(value(arg, iter.next())?.to_string()),
+            "--dump-vram"  => options.dump_vram  = Some(value(arg, iter.next())?.to_string()),
+            "--record"     => options.record     = Some(value(arg, iter.next())?.to_string()),
+            "--wav"        => options.wav        = Some(value(arg, iter.next())?.to_string()),
+            "--stems"      => options.stems      = true,
+            "--record-audio" => options.record_audio = true,
+            "--sample-rate" => options.sample_rate = parse_number(arg, iter.next())?,
+            "--vgm"        => options.vgm        = Some(value(arg, iter.next())?.to_string()),
+            "--vgm-start"  => options.vgm_start  = parse_number(arg, iter.next())?,
+            "--vgm-stop"   => options.vgm_stop   = Some(parse_number(arg, iter.next())?),
+            "--vgm-loop"   => options.vgm_loop   = Some(parse_number(arg, iter.next())?),
+            "--midi"       => options.midi       = Some(value(arg, iter.next())?.to_string()),
+            "--mute"       => options.mute       = parse_channels(arg, value(arg, iter.next())?)?,
+            "--solo"       => options.solo       = parse_channels(arg, value(arg, iter.next())?)?,
+            "--dump-audio" => options.dump_audio = Some(value(arg, iter.next())?.to_string()),
+            "--shades"     => options.dmg_output = DmgOutput::SHADES,
+            "--palette"    => options.palette    = parse_palette(value(arg, iter.next())?)?,
+            "--palette-combo" =>
+            {
+                let name = value(arg, iter.next())?;
+                options.palette = DmgPalette::COMBO(Combo::from_name(name).ok_or(format!("--palette-combo: unknown combo '{}'", name))?);
+            },
+            "--color-correction" => options.correction = true,
+            "--hide"       => hide_layers(&mut options.layers, value(arg, iter.next())?)?,
+            "--no-sprite-limit" => options.layers.sprite_limit = false,
+            "--overlay"    => options.overlay    = true,
+            "--trace"      => options.trace      = true,
+            _              => return Err(format!("run: unknown option '{}'", arg))
+        }
+    }
+
+    if options.scale == 0
+    {
+        return Err("run: --scale must be at least 1".to_string());
+    }
+
+    if !blip::valid_rate(options.sample_rate as f64)
+    {
+        return Err(format!("run: --sample-rate must be {}-{}", blip::MIN_RATE, blip::MAX_RATE));
+    }
+
+    if options.stems && options.wav.is_none()
+    {
+        return Err("run: --stems needs --wav".to_string());
+    }
+
+    if options.record_audio && options.record.is_none()
+    {
+        return Err("run: --record-audio needs --record".to_string());
+    }
+
+    let vgm_stop = options.vgm_stop.unwrap_or(options.frames);
+    if options.vgm.is_none() && (options.vgm_start != 0 || options.vgm_stop.is_some() || options.vgm_loop.is_some())
+    {
+        return Err("run: --vgm-start, --vgm-stop and --vgm-loop need --vgm".to_string());
+    }
+    if options.vgm_start > vgm_stop || vgm_stop > options.frames
+    {
+        return Err("run: VGM log must start before it stops, within --frames".to_string());
+    }
+    if options.vgm_loop.is_some_and(|frame| frame < options.vgm_start || frame > vgm_stop)
+    {
+        return Err("run: --vgm-loop must lie between the VGM start and stop".to_string());
+    }
+
+    if let Some(path) = &options.record
+    {
+        if VideoFormat::from_path(path).is_none()
+        {
+            return Err(format!("--record: '{}' is neither .y4m nor .gif", path));
+        }
+    }
+
+    Ok(options)
+}
+
+fn hide_layers(layers : &mut Layers, names : &str) -> Result<(), String>
+{
+    for name in names.split(',')
+    {
+        match name
+        {
+            "bg"      => layers.bg      = false,
+            "window"  => layers.window  = false,
+            "sprites" => layers.sprites = false,
+            _         => return Err(format!("--hide: unknown layer '{}'", name))
+        }
+    }
+    Ok(())
+}
+
+fn parse_channels(option : &str, list : &str) -> Result<Vec<Channel>, String>
+{
+    list.split(',')
+        .map(|number| number.parse().ok().and_then(Channel::from_number)
+                            .ok_or(format!("{}: unknown channel '{}', expected 1-4", option, number)))
+        .collect()
+}
+
+fn parse_palette(name : &str) -> Result<DmgPalette, String>
+{
+    match name
+    {
+        "gray" | "grey" => Ok(DmgPalette::GRAYSCALE),
+        "green"         => Ok(DmgPalette::GREEN),
+        "pocket"        => Ok(DmgPalette::POCKET),
+        "light"         => Ok(DmgPalette::LIGHT),
+        "auto"          => Ok(DmgPalette::COLORIZE),
+        _ => palette::parse_custom(name).map(DmgPalette::CUSTOM)
+                                        .ok_or(format!("--palette: unknown palette '{}'", name))
+    }
+}
+
+fn value<'a>(option : &str, value : Option<&'a String>) -> Result<&'a str, String>
+{
+    value.map(|v| v.as_str()).ok_or(format!("{}: missing value", option))
+}
+
+fn parse_number<T : std::str::FromStr>(option : &str, arg : Option<&String>) -> Result<T, String>
+{
+    let raw = value(option, arg)?;
+    raw.parse().map_err(|_| format!("{}: invalid number '{}'", option, raw))
+}
@@ -1,38 +1,792 @@
+use std::path::Path;
+
+use crate::apu::blip;
+use crate::apu::scope::Channel;
+use crate::apu::scope::Visualization;
+use crate::audio;
+use crate::audio::midi;
+use crate::audio::vgm;
 use crate::cart::Cart;
 use crate::cpu::CPU;
+use crate::cpu_enums::Reg;
+use crate::gbs;
+use crate::gbs::Gbs;
+use crate::image::filter::Filter;
+use crate::image::ghosting::FrameBlender;
+use crate::image::ghosting::Ghosting;
+use crate::image::DmgOutput;
+use crate::image::Image;
+use crate::io::IO;
 use crate::mem::Mem;
+use crate::palette::DmgPalette;
+use crate::palette::OutputPalette;
+use crate::ppu::Layers;
+use crate::ppu::Renderer;
+use crate::ppu::SCREEN_HEIGHT;
+use crate::ppu::SCREEN_WIDTH;
+use crate::video::Recorder;
+
+const OVERLAY_SPRITE : [u8; 3] = [0x00, 0xFF, 0x00];
+const OVERLAY_WINDOW : [u8; 3] = [0xFF, 0x00, 0xFF];
 
 pub struct Console
 {
     cart : Cart,
     cpu  : CPU,
-    mem  : Mem
+    mem  : Mem,
+    io   : IO,
+
+    palette     : OutputPalette,
+    dmg_palette : DmgPalette,
+    filter      : Filter,
+    ghosting    : FrameBlender,
+    overlay     : bool,
+
+    recorder       : Option<Recorder>,
+    audio_recorder : Option<audio::Recorder>,
+    vgm            : Option<vgm::Writer>,
+    midi           : Option<midi::Writer>,
+
+    gbs        : Option<Gbs>,
+    gbs_driver : Option<gbs::Driver>
 }
 
 impl Console
 {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self
     {
         Console
         {
             cart : Cart::new(),
             cpu  : CPU::new(),
-            mem  : Mem::new()
+            mem  : Mem::new(),
+            io   : IO::new(),
+
+            palette     : OutputPalette::new(),
+            dmg_palette : DmgPalette::GRAYSCALE,
+            filter      : Filter::NEAREST,
+            ghosting    : FrameBlender::new(Ghosting::OFF),
+            overlay     : false,
+
+            recorder       : None,
+            audio_recorder : None,
+            vgm            : None,
+            midi           : None,
+
+            gbs        : None,
+            gbs_driver : None
+        }
+    }
+
+    pub fn framebuffer(&self) -> &[u16]
+    {
+        self.io.ppu.framebuffer()
+    }
+
+    pub fn set_renderer(&mut self, renderer : Renderer)
+    {
+        self.io.ppu.set_renderer(renderer);
+    }
+
+    // COLORIZE is resolved against the loaded cart, again on every load.
+    pub fn set_dmg_palette(&mut self, palette : DmgPalette)
+    {
+        self.dmg_palette = palette;
+        self.palette.set_dmg(palette, &self.cart);
+    }
+
+    pub fn set_color_correction(&mut self, enabled : bool)
+    {
+        self.palette.set_color_correction(enabled);
+    }
+
+    pub fn palette(&self) -> &OutputPalette
+    {
+        &self.palette
+    }
+
+    // Used for screenshots, recordings and frontends alike.
+    pub fn set_filter(&mut self, filter : Filter)
+    {
+        self.filter = filter;
+    }
+
+    pub fn filter(&self) -> Filter
+    {
+        self.filter
+    }
+
+    // Blends frames over time like the real LCD. Keep it off for accuracy
+    // tests. Raw DMG shade output is never blended.
+    pub fn set_ghosting(&mut self, mode : Ghosting)
+    {
+        self.ghosting = FrameBlender::new(mode);
+    }
+
+    pub fn ghosting(&self) -> Ghosting
+    {
+        self.ghosting.mode()
+    }
+
+    // Hides BG, window or sprites and lifts the sprite per line limit in
+    // the output. Emulation is not affected.
+    pub fn set_layers(&mut self, layers : Layers)
+    {
+        self.io.ppu.set_layers(layers);
+    }
+
+    pub fn layers(&self) -> Layers
+    {
+        self.io.ppu.layers()
+    }
+
+    // Outlines sprites and the window area in RGB output.
+    pub fn set_overlay(&mut self, overlay : bool)
+    {
+        self.overlay = overlay;
+    }
+
+    // Held buttons as a mask of joypad::Button bits.
+    pub fn set_buttons(&mut self, pressed : u8)
+    {
+        self.io.set_buttons(pressed);
+    }
+
+    pub fn set_trace(&mut self, trace : bool)
+    {
+        self.cpu.set_trace(trace);
+    }
+
+    // Enables audio output at the host rate, or retunes it while running.
+    // Frames queue up until read, the oldest get dropped after half a second.
+    pub fn set_audio_rate(&mut self, rate : f64) -> bool
+    {
+        if !blip::valid_rate(rate)
+        {
+            eprintln!("Audio rate {} Hz out of range {}-{}", rate, blip::MIN_RATE, blip::MAX_RATE);
+            return false;
+        }
+        self.io.apu.set_sample_rate(rate);
+        true
+    }
+
+    pub fn audio_rate(&self) -> Option<f64>
+    {
+        self.io.apu.sample_rate()
+    }
+
+    pub fn disable_audio(&mut self)
+    {
+        self.io.apu.disable_output();
+    }
+
+    // Stereo frames ready to read.
+    pub fn audio_frames(&self) -> usize
+    {
+        self.io.apu.samples().len()
+    }
+
+    // Buffer fill level from 0.0 to 1.0, for dynamic rate control.
+    pub fn audio_fill(&self) -> f32
+    {
+        self.io.apu.samples().fill()
+    }
+
+    // Interleaved stereo, returns the number of frames written.
+    pub fn read_audio_f32(&mut self, out : &mut [f32]) -> usize
+    {
+        self.io.apu.samples_mut().read_f32(out)
+    }
+
+    pub fn read_audio_i16(&mut self, out : &mut [i16]) -> usize
+    {
+        self.io.apu.samples_mut().read_i16(out)
+    }
+
+    // Mute and solo only affect the mix, WAV stems keep every channel.
+    pub fn set_channel_muted(&mut self, channel : Channel, muted : bool)
+    {
+        self.io.apu.set_muted(channel, muted);
+    }
+
+    pub fn set_channel_solo(&mut self, channel : Channel, solo : bool)
+    {
+        self.io.apu.set_solo(channel, solo);
+    }
+
+    // Per channel frequency, volume, duty and scope plus wave RAM.
+    pub fn audio_visualization(&self) -> Visualization
+    {
+        self.io.apu.visualization()
+    }
+
+    pub fn dump_audio(&self, path : &str) -> bool
+    {
+        match std::fs::write(path, self.audio_visualization().listing())
+        {
+            Ok(()) =>
+            {
+                println!("Dumped audio state: {}", path);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to dump audio state to '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    // Current frame as an image, run through the output filter and scaled
+    // by an integer factor.
+    pub fn screenshot(&self, scale : usize, output : DmgOutput) -> Image
+    {
+        let shades    = output == DmgOutput::SHADES && !self.io.ppu.cgb();
+        let mut frame = match (self.ghosting.output(), shades)
+        {
+            (Some(blended), false) => blended.to_rgb(),
+            _ => Image::from_framebuffer(self.framebuffer(), self.io.ppu.cgb(), output, &self.palette)
+        };
+
+        if self.overlay && !shades
+        {
+            self.draw_overlay(&mut frame);
+        }
+        self.filter.apply(&frame, scale)
+    }
+
+    fn draw_overlay(&self, frame : &mut Image)
+    {
+        let ppu = &self.io.ppu;
+        if let Some((x, y)) = ppu.window_origin()
+        {
+            frame.outline_rect(x, y, SCREEN_WIDTH as i32 - x, SCREEN_HEIGHT as i32 - y, OVERLAY_WINDOW);
+        }
+        for (x, y, width, height) in ppu.sprite_bounds()
+        {
+            frame.outline_rect(x, y, width, height, OVERLAY_SPRITE);
+        }
+    }
+
+    // Writes the current frame as PNG, or PPM/PGM for .ppm/.pgm paths.
+    pub fn save_screenshot(&self, path : &str, scale : usize, output : DmgOutput) -> bool
+    {
+        match self.screenshot(scale, output).save(path)
+        {
+            Ok(()) =>
+            {
+                println!("Saved: {}", path);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to write screenshot '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    // Writes tile sheet, both tile maps, OAM and palette views into a directory.
+    // Only the live PPU can be dumped: there are no save states to restore
+    // one from yet.
+    pub fn dump_vram(&self, dir : &str) -> bool
+    {
+        let ppu = &self.io.ppu;
+        let dir = Path::new(dir);
+
+        let result = std::fs::create_dir_all(dir)
+            .and_then(|_| ppu.tile_sheet().save(&dump_path(dir, "tiles.png")))
+            .and_then(|_| ppu.tile_map(0x9800).save(&dump_path(dir, "map_9800.png")))
+            .and_then(|_| ppu.tile_map(0x9C00).save(&dump_path(dir, "map_9C00.png")))
+            .and_then(|_| ppu.oam_sheet().save(&dump_path(dir, "oam.png")))
+            .and_then(|_| std::fs::write(dump_path(dir, "oam.txt"), ppu.oam_listing()))
+            .and_then(|_| ppu.palette_swatches().save(&dump_path(dir, "palettes.png")));
+
+        match result
+        {
+            Ok(()) =>
+            {
+                println!("Dumped VRAM: {}", dir.display());
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to dump VRAM to '{}': {}", dir.display(), e);
+                false
+            }
+        }
+    }
+
+    // Records every completed frame to a .y4m or .gif file until
+    // stop_recording. Replaces a running recording.
+    // With `audio_rate` the sound is recorded alongside, see Recorder.
+    pub fn start_recording(&mut self, path : &str, scale : usize, audio_rate : Option<u32>) -> bool
+    {
+        self.stop_recording();
+        if let Some(rate) = audio_rate
+        {
+            if !blip::valid_rate(rate as f64)
+            {
+                eprintln!("Failed to start recording '{}': audio rate {} Hz out of range", path, rate);
+                return false;
+            }
+            if !self.start_capture(rate)
+            {
+                eprintln!("Failed to start recording '{}': audio is already captured at another rate", path);
+                return false;
+            }
+        }
+
+        match Recorder::create(path, scale, audio_rate)
+        {
+            Ok(recorder) =>
+            {
+                self.recorder = Some(recorder);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to start recording '{}': {}", path, e);
+                self.release_capture();
+                false
+            }
+        }
+    }
+
+    pub fn stop_recording(&mut self) -> bool
+    {
+        self.record_audio();
+        let Some(recorder) = self.recorder.take() else { return true; };
+        self.release_capture();
+
+        let path  = recorder.path().to_string();
+        let audio = recorder.audio_path().map(|audio| format!(" and {}", audio)).unwrap_or_default();
+        match recorder.finish()
+        {
+            Ok(frames) =>
+            {
+                println!("Recorded {} frames: {}{}", frames, path, audio);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to write recording '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    pub fn recording(&self) -> bool
+    {
+        self.recorder.is_some()
+    }
+
+    // Records the audio mix to a WAV file at `rate` Hz, with `stems` also
+    // every channel to its own file next to it.
+    pub fn start_audio_recording(&mut self, path : &str, rate : u32, stems : bool) -> bool
+    {
+        self.stop_audio_recording();
+        if !blip::valid_rate(rate as f64)
+        {
+            eprintln!("Failed to start audio recording '{}': rate {} Hz out of range", path, rate);
+            return false;
+        }
+        if !self.start_capture(rate)
+        {
+            eprintln!("Failed to start audio recording '{}': audio is already captured at another rate", path);
+            return false;
+        }
+
+        match audio::Recorder::create(path, rate, stems)
+        {
+            Ok(recorder) =>
+            {
+                self.audio_recorder = Some(recorder);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to start audio recording '{}': {}", path, e);
+                self.release_capture();
+                false
+            }
+        }
+    }
+
+    pub fn stop_audio_recording(&mut self) -> bool
+    {
+        self.record_audio();
+        let Some(recorder) = self.audio_recorder.take() else { return true; };
+        self.release_capture();
+
+        let path = recorder.path().to_string();
+        let rate = recorder.rate();
+        match recorder.finish()
+        {
+            Ok(frames) =>
+            {
+                println!("Recorded {:.2} s of audio: {}", frames as f64 / rate as f64, path);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to write audio recording '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    pub fn audio_recording(&self) -> bool
+    {
+        self.audio_recorder.is_some()
+    }
+
+    // Logs every sound register and wave RAM write to a .vgm file, starting
+    // from the current APU state.
+    pub fn start_vgm_log(&mut self, path : &str) -> bool
+    {
+        self.stop_vgm_log();
+        let apu = &mut self.io.apu;
+        match vgm::Writer::create(path, apu.cycles(), &apu.state_writes())
+        {
+            Ok(writer) =>
+            {
+                apu.start_log();
+                self.vgm = Some(writer);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to start VGM log '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    // Playback loops back to here after the end of the log.
+    pub fn mark_vgm_loop(&mut self) -> bool
+    {
+        self.log_vgm();
+        let Some(vgm) = &mut self.vgm else
+        {
+            eprintln!("No VGM log running");
+            return false;
+        };
+        vgm.mark_loop(self.io.apu.cycles());
+        true
+    }
+
+    pub fn stop_vgm_log(&mut self) -> bool
+    {
+        self.log_vgm();
+        self.io.apu.stop_log();
+        let Some(vgm) = self.vgm.take() else { return true; };
+
+        let path = vgm.path().to_string();
+        match vgm.finish(self.io.apu.cycles())
+        {
+            Ok(samples) =>
+            {
+                println!("Logged {:.2} s of VGM: {}", samples as f64 / vgm::VGM_RATE as f64, path);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to write VGM log '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    pub fn vgm_logging(&self) -> bool
+    {
+        self.vgm.is_some()
+    }
+
+    // Converts what the channels play into a MIDI file, one track each.
+    pub fn start_midi(&mut self, path : &str) -> bool
+    {
+        self.stop_midi();
+        match midi::Writer::create(path, self.io.apu.cycles())
+        {
+            Ok(writer) =>
+            {
+                self.midi = Some(writer);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to start MIDI export '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    pub fn stop_midi(&mut self) -> bool
+    {
+        let Some(midi) = self.midi.take() else { return true; };
+
+        let path = midi.path().to_string();
+        match midi.finish(self.io.apu.cycles())
+        {
+            Ok(()) =>
+            {
+                println!("Exported MIDI: {}", path);
+                true
+            },
+            Err(e) =>
+            {
+                eprintln!("Failed to write MIDI export '{}': {}", path, e);
+                false
+            }
+        }
+    }
+
+    fn end_frame(&mut self)
+    {
+        if self.ghosting.mode() != Ghosting::OFF
+        {
+            let frame = Image::from_framebuffer(self.framebuffer(), self.io.ppu.cgb(), DmgOutput::RGB, &self.palette);
+            self.ghosting.push(&frame);
+        }
+
+        if self.recorder.is_some()
+        {
+            self.record_frame();
+        }
+
+        self.record_audio();
+        self.log_vgm();
+    }
+
+    fn log_vgm(&mut self)
+    {
+        if let Some(vgm) = &mut self.vgm
+        {
+            vgm.push(&self.io.apu.take_log());
+        }
+    }
+
+    // The WAV recording and a video's audio track share the APU capture,
+    // and with it the sample rate.
+    fn start_capture(&mut self, rate : u32) -> bool
+    {
+        match self.io.apu.capture_rate()
+        {
+            Some(current) => current == rate as f64,
+            None          =>
+            {
+                self.io.apu.start_capture(rate);
+                true
+            }
+        }
+    }
+
+    fn release_capture(&mut self)
+    {
+        let video_audio = self.recorder.as_ref().is_some_and(|r| r.audio_rate().is_some());
+        if self.audio_recorder.is_none() && !video_audio
+        {
+            self.io.apu.stop_capture();
+        }
+    }
+
+    fn record_audio(&mut self)
+    {
+        if self.io.apu.capture_rate().is_none()
+        {
+            return;
+        }
+
+        let frames = self.io.apu.take_captured();
+        if let Some(recorder) = &mut self.audio_recorder
+        {
+            if let Err(e) = recorder.push(&frames)
+            {
+                eprintln!("Failed to write audio recording '{}': {}", recorder.path(), e);
+                self.audio_recorder = None;
+            }
+        }
+        if let Some(recorder) = &mut self.recorder
+        {
+            if let Err(e) = recorder.push_audio(&frames)
+            {
+                eprintln!("Failed to write recording '{}': {}", recorder.path(), e);
+                self.recorder = None;
+            }
+        }
+        self.release_capture();
+    }
+
+    fn record_frame(&mut self)
+    {
+        let Some(recorder) = &self.recorder else { return; };
+
+        let image = self.screenshot(recorder.scale(), DmgOutput::RGB);
+        if let Some(recorder) = &mut self.recorder
+        {
+            if let Err(e) = recorder.push(&image)
+            {
+                eprintln!("Failed to write recording '{}': {}", recorder.path(), e);
+                self.recorder = None;
+            }
         }
     }
 
+    pub fn load(&mut self, rom_path : &str) -> bool
+    {
+        let loaded = self.cart.load(rom_path);
+        if loaded
+        {
+            self.cart.print_info();
+            self.io.ppu.set_cgb(self.cart.cgb());
+            self.io.apu.set_cgb(self.cart.cgb());
+            self.io.timer.set_cgb(self.cart.cgb());
+            self.cpu.reset(&self.cart);
+            self.palette.set_dmg(self.dmg_palette, &self.cart);
+            self.ghosting.reset();
+            self.gbs        = None;
+            self.gbs_driver = None;
+        }
+        loaded
+    }
+
+    // Loads a GBS file and starts `track`, or the file's first song.
+    pub fn load_gbs(&mut self, path : &str, track : Option<u8>) -> bool
+    {
+        let gbs = match Gbs::load(path)
+        {
+            Ok(gbs) => gbs,
+            Err(e) =>
+            {
+                eprintln!("Failed to load GBS '{}': {}", path, e);
+                return false;
+            }
+        };
+
+        gbs.print_info();
+        self.cart.load_banked(gbs.image());
+        self.ghosting.reset();
+
+        let track = track.unwrap_or(gbs.first_song);
+        self.gbs  = Some(gbs);
+        self.play_track(track)
+    }
+
+    // Runs init for a 1 based track of the loaded GBS file, play then gets
+    // called at the file's rate. RAM and all I/O start out fresh, with the
+    // header's TMA and TAC in the timer. TAC bit 7 asks for a CGB in double
+    // speed.
+    pub fn play_track(&mut self, track : u8) -> bool
+    {
+        let Some(gbs) = &self.gbs else
+        {
+            eprintln!("No GBS file loaded");
+            return false;
+        };
+        if track == 0 || track > gbs.song_count
+        {
+            eprintln!("Track {} out of range 1-{}", track, gbs.song_count);
+            return false;
+        }
+
+        let double_speed = gbs.tac & 0x80 != 0;
+        self.mem = Mem::new();
+        self.io.reset();
+        self.io.ppu.set_cgb(double_speed);
+        self.io.apu.set_cgb(double_speed);
+        self.io.timer.set_cgb(double_speed);
+        self.io.set_double_speed(double_speed);
+
+        self.io.apu.write_reg(0xFF26, 0x00);
+        self.io.apu.write_reg(0xFF26, 0x80);
+        self.io.apu.write_reg(0xFF24, 0x77);
+        self.io.apu.write_reg(0xFF25, 0xFF);
+        self.io.write8(0xFF06, gbs.tma);
+        self.io.write8(0xFF07, gbs.tac);
+
+        self.cpu.reset(&self.cart);
+        self.cpu.set_reg(Reg::SP, gbs.sp);
+        self.cpu.set_reg(Reg::A, (track - 1) as u16);
+        self.cpu.set_reg(Reg::IE, 0x00);
+        self.cpu.call(&mut self.cart, &mut self.mem, &mut self.io, gbs.init, gbs::IDLE_ADDRESS);
+
+        self.gbs_driver = Some(gbs::Driver::new(gbs, self.io.clock()));
+        println!("Playing track {} of {}", track, gbs.song_count);
+        true
+    }
+
+    pub fn step(&mut self)
+    {
+        if let Some(driver) = &mut self.gbs_driver
+        {
+            let idle = self.cpu.halted_at(gbs::IDLE_ADDRESS);
+            if let Some(play) = driver.poll(&mut self.io, idle)
+            {
+                self.cpu.call(&mut self.cart, &mut self.mem, &mut self.io, play, gbs::IDLE_ADDRESS);
+            }
+        }
+
+        let frame = self.io.ppu.frames();
+        self.cpu.step(&mut self.cart, &mut self.mem, &mut self.io);
+
+        if let Some(midi) = &mut self.midi
+        {
+            midi.poll(&self.io.apu);
+        }
+
+        if self.io.ppu.frames() != frame
+        {
+            self.end_frame();
+        }
+    }
+
+    pub fn run_frames(&mut self, frames : u64)
+    {
+        let target = self.io.ppu.frames() + frames;
+        while self.io.ppu.frames() < target
+        {
+            self.step();
+        }
+    }
+
+    // Runs until the ROM executes LD B,B, giving up after max_frames.
+    pub fn run_until_breakpoint(&mut self, max_frames : u64) -> bool
+    {
+        let limit = self.io.ppu.frames() + max_frames;
+        while self.io.ppu.frames() < limit
+        {
+            self.step();
+            if self.cpu.take_breakpoint()
+            {
+                return true;
+            }
+        }
+        false
+    }
+
     pub fn start(&mut self, rom_path : &str)
     {
         let loaded = self.cart.load(rom_path);
         if loaded
         {
             self.cart.print_info();
+            self.io.ppu.set_cgb(self.cart.cgb());
+            self.io.apu.set_cgb(self.cart.cgb());
+            self.io.timer.set_cgb(self.cart.cgb());
 
             self.cpu.start
             (
                 &mut self.cart,
-                &mut self.mem
+                &mut self.mem,
+                &mut self.io
             );
         }
     }
-}
\ No newline at end of file
+}
+
+fn dump_path(dir : &Path, name : &str) -> String
+{
+    dir.join(name).to_string_lossy().into_owned()
+}
@@ -18,6 +18,7 @@ use crate::instructions::load::*;
 
 use crate::bus::*;
 use crate::cart::Cart;
+use crate::io::IO;
 use crate::mem::Mem;
 use crate::regs::Regs;
 
@@ -27,6 +28,11 @@ pub struct CPU
     regs   : Regs, 
     mapper : Mapper,
     int_en : bool,
+    halted : bool,
+    trace  : bool,
+
+    // Set when LD B,B runs, the software breakpoint used by test ROMs.
+    breakpoint : bool,
 
     curr_opcode : u8,
 
@@ -38,6 +44,7 @@ pub struct CPU
 
 impl CPU
 {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self
     {
         CPU
@@ -45,6 +52,10 @@ impl CPU
             regs   : Regs::new(),
             mapper : Mapper::new(),
             int_en : true,
+            halted : false,
+            trace  : true,
+
+            breakpoint : false,
 
             curr_opcode : 0x00,
 
@@ -55,17 +66,53 @@ impl CPU
         }
     }
     
-    pub fn start(&mut self, cart : &mut Cart, mem : &mut Mem)
+    pub fn start(&mut self, cart : &mut Cart, mem : &mut Mem, io : &mut IO)
     {
-        self.regs.write(Reg::A, 0x01);
-        self.regs.write(Reg::PC, 0x0100);
+        self.reset(cart);
 
         loop
         {
-            self.step(cart, mem);
+            self.step(cart, mem, io);
         }
     }
 
+    // Register state as left behind by the boot ROM.
+    pub fn reset(&mut self, cart : &Cart)
+    {
+        // The CGB boot ROM hands over with A = 0x11 so games can detect it.
+        self.regs.write(Reg::A, if cart.cgb() { 0x11 } else { 0x01 });
+        self.regs.write(Reg::PC, 0x0100);
+    }
+
+    pub fn set_reg(&mut self, reg : Reg, value : u16)
+    {
+        self.regs.write(reg, value);
+    }
+
+    // Calls a subroutine from outside the program, returning to `ret`.
+    // Used by the GBS driver for init and play.
+    pub fn call(&mut self, cart : &mut Cart, mem : &mut Mem, io : &mut IO, address : u16, ret : u16)
+    {
+        bus::push16(cart, mem, &mut self.regs, io, ret);
+        self.regs.write(Reg::PC, address);
+        self.halted = false;
+    }
+
+    // Whether the CPU sits in the HALT at `address`.
+    pub fn halted_at(&self, address : u16) -> bool
+    {
+        self.halted && self.regs.read(Reg::PC) == address.wrapping_add(1)
+    }
+
+    // Per instruction trace on stdout.
+    pub fn set_trace(&mut self, trace : bool) { self.trace = trace; }
+
+    // Whether LD B,B ran since the last call.
+    pub fn take_breakpoint(&mut self) -> bool
+    {
+        std::mem::take(&mut self.breakpoint)
+    }
+
     fn print_step(&self, cart : &mut Cart, inst : &Instruction)
     {
         let pc = self.regs.read(Reg::PC);
@@ -88,18 +135,45 @@ impl CPU
         );
     }
 
-    fn step(&mut self, cart : &mut Cart, mem : &mut Mem)
+    pub fn step(&mut self, cart : &mut Cart, mem : &mut Mem, io : &mut IO)
     {
+        // The CPU sits out HDMA blocks
+        if io.hdma.stalling()
+        {
+            emu_cycles(cart, mem, io, 1);
+            return;
+        }
+
+        if self.halted
+        {
+            emu_cycles(cart, mem, io, 1);
+            if io.interrupt_pending(self.regs.read_ie())
+            {
+                self.halted = false;
+                io.hdma.set_paused(false);
+            }
+            return;
+        }
+
         self.clear();
-        self.fetch_instruction(cart);
+        self.fetch_instruction(cart, mem, io);
+        emu_cycles(cart, mem, io, 1);
 
         let instruction = self.mapper.instruction_from_opcode(self.curr_opcode);
-        self.print_step(cart,  &instruction);
+        if self.trace
+        {
+            self.print_step(cart, instruction);
+        }
 
         self.regs.inc_pc(1);
 
-        self.fetch_data(cart, mem);
-        self.execute(cart, mem);
+        if self.curr_opcode == 0x40
+        {
+            self.breakpoint = true;
+        }
+
+        self.fetch_data(cart, mem, io);
+        self.execute(cart, mem, io);
     }
     fn clear(&mut self)
     {
@@ -107,47 +181,52 @@ impl CPU
         self.ctx_mem_addr = 0;
         self.dest_is_mem = false;
     }
-    fn fetch_instruction(&mut self, cart : &mut Cart)
+    // Goes through the bus like any other read, so code in HRAM runs and
+    // OAM DMA conflicts apply to opcodes too.
+    fn fetch_instruction(&mut self, cart : &mut Cart, mem : &mut Mem, io : &mut IO)
     {
-        self.curr_opcode = cart.read8(self.regs.read(Reg::PC));
+        self.curr_opcode = bus::read8(cart, mem, &self.regs, io, self.regs.read(Reg::PC));
     }
-    fn fetch_data(&mut self, cart : &mut Cart, mem : &mut Mem)
+    fn fetch_data(&mut self, cart : &mut Cart, mem : &mut Mem, io : &mut IO)
     {
         let instruction = self.mapper.instruction_from_opcode(self.curr_opcode);
         self.dest_is_mem = false;
         match instruction.addr_mode
         {
-            AddrMode::IMP    => return,
+            AddrMode::IMP    => {},
 
             AddrMode::R_D16 |
             AddrMode::D16 =>
             {
                 let address = self.regs.read(Reg::PC);
-                self.ctx_data = bus::read16(cart, mem, &mut self.regs, address);
+                self.ctx_data = bus::read16(cart, mem, &self.regs, io, address);
                 self.regs.inc_pc(2);
+                emu_cycles(cart, mem, io, 2);
             },
 
             AddrMode::A16_R |
             AddrMode::D16_R  =>
             {
                 let pc      = self.regs.read(Reg::PC);
-                let address = bus::read16(cart, mem, &mut self.regs, pc);
+                let address = bus::read16(cart, mem, &self.regs, io, pc);
                 let value   = self.regs.read(instruction.reg_2);
 
                 self.dest_is_mem  = true;
                 self.ctx_mem_addr = address;
                 self.ctx_data     = value; 
                 self.regs.inc_pc(2);
+                emu_cycles(cart, mem, io, 2);
             },
 
-            AddrMode::R_R    => self.TODO_fd(instruction),
+            AddrMode::R_R    => self.ctx_data = self.regs.read(instruction.reg_2),
             AddrMode::MR_R   => self.TODO_fd(instruction),
             AddrMode::R      => self.ctx_data = self.regs.read(instruction.reg_1),
             AddrMode::R_D8   =>
             {
                 let pc = self.regs.read(Reg::PC);
-                self.ctx_data = bus::read16(cart, mem, &mut self.regs, pc);
+                self.ctx_data = bus::read16(cart, mem, &self.regs, io, pc);
                 self.regs.inc_pc(1);
+                emu_cycles(cart, mem, io, 1);
             }
             AddrMode::R_MR   => self.TODO_fd(instruction),
             AddrMode::R_HLI  => self.TODO_fd(instruction),
@@ -158,13 +237,14 @@ impl CPU
             AddrMode::A8_R   =>
             {
                 let pc      = self.regs.read(Reg::PC);
-                let address = bus::read16(cart, mem, &mut self.regs, pc) as u8;
+                let address = bus::read16(cart, mem, &self.regs, io, pc) as u8;
                 let value   = self.regs.read(instruction.reg_2);
 
                 self.dest_is_mem  = true;
                 self.ctx_mem_addr = address as u16;
                 self.ctx_data     = value; 
                 self.regs.inc_pc(1);
+                emu_cycles(cart, mem, io, 1);
             },
             AddrMode::HL_SPR => self.TODO_fd(instruction),
             AddrMode::D8     => self.TODO_fd(instruction),
@@ -174,9 +254,10 @@ impl CPU
         }
     }
 
-    fn execute(&mut self, cart : &mut Cart, mem : &mut Mem)
+    fn execute(&mut self, cart : &mut Cart, mem : &mut Mem, io : &mut IO)
     {
         let instruction = self.mapper.instruction_from_opcode(self.curr_opcode);
+        let mut cycles  = 0;
         let mut ctx = Context
         {
             inst_type : instruction.inst_type,
@@ -190,10 +271,13 @@ impl CPU
             mem_addr    : self.ctx_mem_addr,
             dest_is_mem : self.dest_is_mem,
             int_en      : &mut self.int_en,
+            halted      : &mut self.halted,
+            cycles      : &mut cycles,
 
             regs   : &mut self.regs,
-            cart   : cart,
-            mem    : mem
+            cart,
+            mem,
+            io
         };
 
         match instruction.inst_type
@@ -206,7 +290,7 @@ impl CPU
             InstType::RLCA => self.TODO_exe("RCLA"),
             InstType::ADD  => self.TODO_exe("ADD"),
             InstType::RRCA => self.TODO_exe("RRCA"),
-            InstType::STOP => self.TODO_exe("STOP"),
+            InstType::STOP => misc::stop(&mut ctx),
             InstType::RLA  => self.TODO_exe("RLA"),
             InstType::JR   => self.TODO_exe("JR"),
             InstType::RRA  => self.TODO_exe("RRA"),
@@ -214,7 +298,7 @@ impl CPU
             InstType::CPL  => self.TODO_exe("CPL"),
             InstType::SCF  => self.TODO_exe("SCF"),
             InstType::CCF  => self.TODO_exe("CCF"),
-            InstType::HALT => self.TODO_exe("HALT"),
+            InstType::HALT => misc::halt(&mut ctx),
             InstType::ADC  => self.TODO_exe("ADC"),
             InstType::SUB  => self.TODO_exe("SUB"),
             InstType::SBC  => self.TODO_exe("SBC"),
@@ -247,11 +331,14 @@ impl CPU
             InstType::RES  => self.TODO_exe("RES"),
             InstType::SET  => self.TODO_exe("SET")
         }
+
+        emu_cycles(cart, mem, io, cycles);
     }
 
     // ==========================
     // TODO
     // ==========================
+    #[allow(non_snake_case)]
     fn TODO_fd(&self, instruction : &Instruction)
     {
         let opcode = format!("Unresolved Fetch Data Opcode {:02X} {} TODO", 
@@ -261,6 +348,7 @@ impl CPU
         panic!("{}", opcode);
     }
     
+    #[allow(non_snake_case)]
     fn TODO_exe(&self, msg : &str)
     {
         let opcode = format!("Unresolved Execute {} TODO", msg);
@@ -268,4 +356,32 @@ impl CPU
         panic!("{}", opcode);
     }
 
-}
\ No newline at end of file
+}
+
+// Advances everything clocked alongside the CPU by a number of M-cycles.
+fn emu_cycles(cart : &Cart, mem : &Mem, io : &mut IO, cycles : u8)
+{
+    for _ in 0..cycles
+    {
+        io.tick(1);
+
+        if let Some((address, index)) = io.dma.tick()
+        {
+            let value = bus::dma_read8(cart, mem, io, address);
+            io.dma.set_last(value);
+            io.ppu.write_oam_dma(index, value);
+        }
+
+        // HDMA moves 16 bytes per 8us: two bytes per M-cycle at normal
+        // speed, one in double speed.
+        let hdma_bytes = if io.double_speed() { 1 } else { 2 };
+        for _ in 0..hdma_bytes
+        {
+            if let Some((source, dest)) = io.hdma.step()
+            {
+                let value = bus::dma_read8(cart, mem, io, source);
+                io.ppu.write_vram_dma(dest, value);
+            }
+        }
+    }
+}
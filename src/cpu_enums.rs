@@ -122,4 +122,13 @@ pub enum RamType
 {
     WRAM,
     HRAM
-}
\ No newline at end of file
+}
+#[derive(Copy, Clone)]
+pub enum Interrupt
+{
+    VBLANK = 0x01,
+    STAT   = 0x02,
+    TIMER  = 0x04,
+    SERIAL = 0x08,
+    JOYPAD = 0x10
+}
@@ -0,0 +1,125 @@
+// OAM DMA: 160 bytes from XX00-XX9F into OAM, one byte per M-cycle,
+// after a one M-cycle start delay.
+pub struct DMA
+{
+    active : bool,
+    source : u8,
+    index  : u8,
+    delay  : u8,
+    last   : u8,
+    // Restarted while moving bytes, the old transfer still owns the bus
+    // during the new one's start delay.
+    restarted : bool
+}
+
+pub const DMA_LENGTH : u8 = 0xA0;
+
+impl DMA
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        DMA
+        {
+            active : false,
+            source : 0xFF,
+            index  : 0x00,
+            delay  : 0x00,
+            last   : 0xFF,
+            restarted : false
+        }
+    }
+
+    pub fn read_reg(&self) -> u8 { self.source }
+
+    // Restarting while a transfer is running keeps the bus blocked.
+    pub fn start(&mut self, value : u8)
+    {
+        self.restarted = self.blocking();
+        self.active = true;
+        self.source = value;
+        self.index  = 0;
+        self.delay  = 1;
+    }
+
+    // The CPU loses the bus once bytes are actually moving.
+    pub fn blocking(&self) -> bool
+    {
+        self.active && (self.delay == 0 || self.restarted)
+    }
+
+    // Returns the (source address, OAM index) pair to copy this M-cycle.
+    pub fn tick(&mut self) -> Option<(u16, u8)>
+    {
+        if !self.active
+        {
+            return None;
+        }
+        if self.delay > 0
+        {
+            self.delay -= 1;
+            if self.delay == 0
+            {
+                self.restarted = false;
+            }
+            return None;
+        }
+
+        let index   = self.index;
+        let address = ((self.source as u16) << 8) | index as u16;
+
+        self.index += 1;
+        if self.index == DMA_LENGTH
+        {
+            self.active = false;
+        }
+
+        Some((address, index))
+    }
+
+    // The byte on the bus, seen by conflicting CPU reads.
+    pub fn last(&self) -> u8 { self.last }
+    pub fn set_last(&mut self, value : u8) { self.last = value; }
+
+    pub fn source_address(&self) -> u16 { (self.source as u16) << 8 }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{DMA, DMA_LENGTH};
+
+    #[test]
+    fn start_delay_then_one_byte_per_cycle()
+    {
+        let mut dma = DMA::new();
+        dma.start(0xC1);
+        assert!(!dma.blocking());
+        assert_eq!(dma.tick(), None);
+
+        assert!(dma.blocking());
+        for index in 0..DMA_LENGTH
+        {
+            assert_eq!(dma.tick(), Some((0xC100 | index as u16, index)));
+        }
+        assert!(!dma.blocking());
+        assert_eq!(dma.tick(), None);
+    }
+
+    #[test]
+    fn restart_keeps_bus_blocked()
+    {
+        let mut dma = DMA::new();
+        dma.start(0xC1);
+        (0..10).for_each(|_| { dma.tick(); });
+
+        // The new transfer starts over after its own delay, but the old one
+        // keeps the bus for that cycle.
+        dma.start(0xD0);
+        assert!(dma.blocking());
+        assert_eq!(dma.tick(), None);
+        assert!(dma.blocking());
+        assert_eq!(dma.tick(), Some((0xD000, 0)));
+        assert_eq!(dma.read_reg(), 0xD0);
+    }
+}
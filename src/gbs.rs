@@ -0,0 +1,209 @@
+use std::fs;
+
+use crate::cpu_enums::Interrupt;
+use crate::io::IO;
+use crate::ppu::DOTS_PER_FRAME;
+use crate::timer::CLOCK_HZ;
+
+// Game Boy Sound System files: a 0x70 byte header followed by code and
+// data that get loaded at the load address.
+//
+// 0x00 : "GBS", version 1
+// 0x04 : Song count, first song (1 based)
+// 0x06 : Load, init and play addresses, initial SP
+// 0x0E : TMA, TAC
+// 0x10 : Title, author, copyright, 32 bytes each
+
+const HEADER_SIZE : usize = 0x70;
+
+// The driver lives below the load address:
+// 0x00 - 0x3F : RST vectors, jump to load + vector
+// 0x40 - 0x60 : Interrupt vectors, RETI
+// 0x70        : Idle loop the init and play calls return to
+pub const IDLE_ADDRESS : u16 = 0x0070;
+const DRIVER_SIZE      : u16 = 0x0080;
+
+const TIMER_CLOCKS : [u32; 4] = [4096, 262144, 65536, 16384];
+
+pub struct Gbs
+{
+    pub song_count : u8,
+    pub first_song : u8,
+    pub load       : u16,
+    pub init       : u16,
+    pub play       : u16,
+    pub sp         : u16,
+    pub tma        : u8,
+    pub tac        : u8,
+    pub title      : String,
+    pub author     : String,
+    pub copyright  : String,
+    data           : Vec<u8>
+}
+
+impl Gbs
+{
+    pub fn load(path : &str) -> Result<Self, String>
+    {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        Gbs::parse(&bytes)
+    }
+
+    pub fn parse(bytes : &[u8]) -> Result<Self, String>
+    {
+        if bytes.len() < HEADER_SIZE || &bytes[0..3] != b"GBS"
+        {
+            return Err("not a GBS file".to_string());
+        }
+        if bytes[3] != 1
+        {
+            return Err(format!("unsupported GBS version {}", bytes[3]));
+        }
+
+        let word = |offset : usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let text = |offset : usize|
+        {
+            let raw = &bytes[offset..offset + 32];
+            let end = raw.iter().position(|b| *b == 0).unwrap_or(raw.len());
+            String::from_utf8_lossy(&raw[..end]).into_owned()
+        };
+
+        let gbs = Gbs
+        {
+            song_count : bytes[4],
+            first_song : bytes[5],
+            load       : word(0x06),
+            init       : word(0x08),
+            play       : word(0x0A),
+            sp         : word(0x0C),
+            tma        : bytes[0x0E],
+            tac        : bytes[0x0F],
+            title      : text(0x10),
+            author     : text(0x30),
+            copyright  : text(0x50),
+            data       : bytes[HEADER_SIZE..].to_vec()
+        };
+
+        if gbs.song_count == 0
+        {
+            return Err("GBS file has no songs".to_string());
+        }
+        if gbs.load < DRIVER_SIZE || gbs.load >= 0x8000
+        {
+            return Err(format!("load address {:04X} outside 0x{:04X}-0x7FFF", gbs.load, DRIVER_SIZE));
+        }
+        Ok(gbs)
+    }
+
+    pub fn print_info(&self)
+    {
+        println!("GBS Loaded");
+        println!("\tTitle     : {}", self.title);
+        println!("\tAuthor    : {}", self.author);
+        println!("\tCopyright : {}", self.copyright);
+        println!("\tSongs     : {} (first {})", self.song_count, self.first_song);
+        println!("\tLoad      : {:04X} Init {:04X} Play {:04X} SP {:04X}", self.load, self.init, self.play, self.sp);
+        println!("\tRate      : {:.2} Hz ({})", CLOCK_HZ as f64 / self.play_period() as f64,
+                 if self.timer_driven() { "timer" } else { "VBlank" });
+    }
+
+    // TAC bit 2 selects the timer interrupt instead of VBlank.
+    pub fn timer_driven(&self) -> bool
+    {
+        self.tac & 0x04 != 0
+    }
+
+    // T-cycles between play calls with the header's TMA and TAC. TAC bit 7
+    // asks for CGB double speed, which runs the timer twice as fast.
+    pub fn play_period(&self) -> u64
+    {
+        if !self.timer_driven()
+        {
+            return DOTS_PER_FRAME as u64;
+        }
+
+        let mut clock = TIMER_CLOCKS[(self.tac & 0x03) as usize] as u64;
+        if self.tac & 0x80 != 0
+        {
+            clock *= 2;
+        }
+        CLOCK_HZ as u64 * (256 - self.tma as u64) / clock
+    }
+
+    // ROM image with the data at the load address and the driver below it,
+    // padded to whole 16 KiB banks.
+    pub fn image(&self) -> Vec<u8>
+    {
+        let end      = self.load as usize + self.data.len();
+        let size     = end.div_ceil(0x4000).max(2) * 0x4000;
+        let mut rom  = vec![0u8; size];
+        rom[self.load as usize..end].copy_from_slice(&self.data);
+
+        for vector in (0x00..0x40).step_by(8)
+        {
+            let [lo, hi] = (self.load + vector as u16).to_le_bytes();
+            rom[vector..vector + 3].copy_from_slice(&[0xC3, lo, hi]);
+        }
+        for vector in (0x40..=0x60).step_by(8)
+        {
+            rom[vector] = 0xD9;
+        }
+
+        // HALT; JP idle
+        let [lo, hi] = IDLE_ADDRESS.to_le_bytes();
+        let idle     = IDLE_ADDRESS as usize;
+        rom[idle..idle + 4].copy_from_slice(&[0x76, 0xC3, lo, hi]);
+        rom
+    }
+}
+
+// Calls play once init has returned: on every timer interrupt for timer
+// driven files, once per frame otherwise. The timer keeps running under
+// init and play, so their TMA, TAC and KEY1 writes change the rate. Play
+// calls that come due while the previous one still runs are held back
+// until it returns, several of them count as one.
+pub struct Driver
+{
+    play  : u16,
+    timer : bool,
+    next  : u64
+}
+
+impl Driver
+{
+    pub fn new(gbs : &Gbs, now : u64) -> Self
+    {
+        Driver
+        {
+            play  : gbs.play,
+            timer : gbs.timer_driven(),
+            next  : now + DOTS_PER_FRAME as u64
+        }
+    }
+
+    // Address to call if a play call is due. The CPU does not dispatch
+    // interrupts, so the timer interrupt is acknowledged here.
+    pub fn poll(&mut self, io : &mut IO, idle : bool) -> Option<u16>
+    {
+        if !idle
+        {
+            return None;
+        }
+
+        if self.timer
+        {
+            return io.acknowledge(Interrupt::TIMER).then_some(self.play);
+        }
+
+        let now = io.clock();
+        if now < self.next
+        {
+            return None;
+        }
+        while self.next <= now
+        {
+            self.next += DOTS_PER_FRAME as u64;
+        }
+        Some(self.play)
+    }
+}
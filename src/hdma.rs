@@ -0,0 +1,213 @@
+// CGB VRAM DMA (HDMA1-HDMA5). General purpose DMA copies everything at
+// once, HBlank DMA copies one 16 byte block per HBlank. Either way the CPU
+// is stalled while a block is in flight.
+#[derive(Copy, Clone, PartialEq)]
+pub enum HdmaMode
+{
+    IDLE,
+    GENERAL,
+    HBLANK
+}
+
+pub const HDMA_BLOCK : u8 = 0x10;
+
+pub struct HDMA
+{
+    source      : u16,
+    dest        : u16,
+    remaining   : u8,
+    mode        : HdmaMode,
+    block_bytes : u8,
+    paused      : bool
+}
+
+impl HDMA
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        HDMA
+        {
+            source      : 0x0000,
+            dest        : 0x8000,
+            remaining   : 0x7F,
+            mode        : HdmaMode::IDLE,
+            block_bytes : 0,
+            paused      : false
+        }
+    }
+
+    pub fn read_reg(&self, address : u16) -> u8
+    {
+        match address
+        {
+            // Bit 7 clear while an HBlank transfer is running, set once it
+            // finished or got cancelled. The low bits are the blocks left - 1.
+            0xFF55 =>
+            {
+                if self.mode == HdmaMode::HBLANK { self.remaining } else { 0x80 | self.remaining }
+            },
+            _ => 0xFF
+        }
+    }
+
+    pub fn write_reg(&mut self, address : u16, value : u8, in_hblank : bool)
+    {
+        match address
+        {
+            0xFF51 => self.source = (self.source & 0x00FF) | ((value as u16) << 8),
+            0xFF52 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            0xFF53 => self.dest   = 0x8000 | (self.dest & 0x00FF) | (((value & 0x1F) as u16) << 8),
+            0xFF54 => self.dest   = (self.dest & 0xFF00) | (value & 0xF0) as u16,
+            0xFF55 =>
+            {
+                if self.mode == HdmaMode::HBLANK && value & 0x80 == 0
+                {
+                    self.mode        = HdmaMode::IDLE;
+                    self.block_bytes = 0;
+                    return;
+                }
+
+                self.remaining = value & 0x7F;
+                if value & 0x80 == 0
+                {
+                    self.mode        = HdmaMode::GENERAL;
+                    self.block_bytes = HDMA_BLOCK;
+                }
+                else
+                {
+                    // Starting inside HBlank moves the first block right away.
+                    self.mode        = HdmaMode::HBLANK;
+                    self.block_bytes = if in_hblank && !self.paused { HDMA_BLOCK } else { 0 };
+                }
+            },
+            _ => {}
+        }
+    }
+
+    pub fn on_hblank(&mut self)
+    {
+        if self.mode == HdmaMode::HBLANK && self.block_bytes == 0 && !self.paused
+        {
+            self.block_bytes = HDMA_BLOCK;
+        }
+    }
+
+    // HBlank blocks are skipped while the CPU sits in HALT.
+    pub fn set_paused(&mut self, paused : bool) { self.paused = paused; }
+
+    pub fn stalling(&self) -> bool { self.block_bytes > 0 }
+
+    // Returns the next (source, VRAM destination) byte to copy.
+    pub fn step(&mut self) -> Option<(u16, u16)>
+    {
+        if self.block_bytes == 0
+        {
+            return None;
+        }
+
+        let pair = (self.source, self.dest);
+        self.source = self.source.wrapping_add(1);
+        self.dest   = 0x8000 | (self.dest.wrapping_add(1) & 0x1FFF);
+
+        self.block_bytes -= 1;
+        if self.block_bytes == 0
+        {
+            self.remaining = self.remaining.wrapping_sub(1) & 0x7F;
+            if self.remaining == 0x7F
+            {
+                self.mode = HdmaMode::IDLE;
+            }
+            else if self.mode == HdmaMode::GENERAL
+            {
+                self.block_bytes = HDMA_BLOCK;
+            }
+        }
+
+        Some(pair)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{HDMA, HDMA_BLOCK};
+
+    fn setup(hdma : &mut HDMA)
+    {
+        hdma.write_reg(0xFF51, 0xC0, false);
+        hdma.write_reg(0xFF52, 0x00, false);
+        hdma.write_reg(0xFF53, 0x00, false);
+        hdma.write_reg(0xFF54, 0x00, false);
+    }
+
+    fn drain(hdma : &mut HDMA) -> usize
+    {
+        let mut bytes = 0;
+        while hdma.step().is_some()
+        {
+            bytes += 1;
+        }
+        bytes
+    }
+
+    #[test]
+    fn general_copies_every_block()
+    {
+        let mut hdma = HDMA::new();
+        setup(&mut hdma);
+        hdma.write_reg(0xFF55, 0x02, false);
+        assert!(hdma.stalling());
+        assert_eq!(drain(&mut hdma), 3 * HDMA_BLOCK as usize);
+        assert_eq!(hdma.read_reg(0xFF55), 0xFF);
+    }
+
+    #[test]
+    fn hblank_copies_one_block_per_hblank()
+    {
+        let mut hdma = HDMA::new();
+        setup(&mut hdma);
+        hdma.write_reg(0xFF55, 0x81, false);
+        assert!(!hdma.stalling());
+
+        hdma.on_hblank();
+        assert_eq!(drain(&mut hdma), HDMA_BLOCK as usize);
+        assert_eq!(hdma.read_reg(0xFF55), 0x00);
+
+        hdma.on_hblank();
+        assert_eq!(drain(&mut hdma), HDMA_BLOCK as usize);
+        assert_eq!(hdma.read_reg(0xFF55), 0xFF);
+
+        hdma.on_hblank();
+        assert!(!hdma.stalling());
+    }
+
+    #[test]
+    fn halt_skips_hblank_blocks()
+    {
+        let mut hdma = HDMA::new();
+        setup(&mut hdma);
+        hdma.write_reg(0xFF55, 0x81, false);
+
+        hdma.set_paused(true);
+        hdma.on_hblank();
+        assert!(!hdma.stalling());
+        assert_eq!(hdma.read_reg(0xFF55), 0x01);
+
+        hdma.set_paused(false);
+        hdma.on_hblank();
+        assert_eq!(drain(&mut hdma), HDMA_BLOCK as usize);
+    }
+
+    #[test]
+    fn cancel_reports_blocks_left()
+    {
+        let mut hdma = HDMA::new();
+        setup(&mut hdma);
+        hdma.write_reg(0xFF55, 0x83, true);
+        assert_eq!(drain(&mut hdma), HDMA_BLOCK as usize);
+        hdma.write_reg(0xFF55, 0x00, false);
+        assert!(!hdma.stalling());
+        assert_eq!(hdma.read_reg(0xFF55), 0x82);
+    }
+}
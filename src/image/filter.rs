@@ -0,0 +1,294 @@
+use crate::image::ColorType;
+use crate::image::Image;
+
+// Post-processing applied to output frames before any further integer
+// scaling. Emulated state never sees these.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Filter
+{
+    NEAREST,
+    SCALE2X,
+    SCALE3X,
+    HQ2X,
+    // Each pixel as a dot with a darker gap around it, `scale` pixels wide.
+    LCD
+}
+
+impl Filter
+{
+    pub fn from_name(name : &str) -> Option<Self>
+    {
+        match name
+        {
+            "nearest" | "none" => Some(Filter::NEAREST),
+            "scale2x"          => Some(Filter::SCALE2X),
+            "scale3x"          => Some(Filter::SCALE3X),
+            "hq2x"             => Some(Filter::HQ2X),
+            "lcd"              => Some(Filter::LCD),
+            _                  => None
+        }
+    }
+
+    // Applies the filter, then scales up by `scale`. The LCD grid uses the
+    // scale as its cell size instead, at least 3 pixels.
+    pub fn apply(&self, image : &Image, scale : usize) -> Image
+    {
+        match self
+        {
+            Filter::NEAREST => image.scale(scale),
+            Filter::SCALE2X => scale2x(image).scale(scale),
+            Filter::SCALE3X => scale3x(image).scale(scale),
+            Filter::HQ2X    => hq2x(&image.to_rgb()).scale(scale),
+            Filter::LCD     => lcd_grid(&image.to_rgb(), scale.max(3))
+        }
+    }
+}
+
+// Pixel access with edges clamped, so borders repeat outwards.
+struct Source<'a>
+{
+    image    : &'a Image,
+    channels : usize
+}
+
+impl<'a> Source<'a>
+{
+    fn new(image : &'a Image) -> Self
+    {
+        Source { image, channels : image.color.channels() }
+    }
+
+    fn get(&self, x : isize, y : isize) -> &'a [u8]
+    {
+        let x     = x.clamp(0, self.image.width as isize - 1) as usize;
+        let y     = y.clamp(0, self.image.height as isize - 1) as usize;
+        let index = (y * self.image.width + x) * self.channels;
+        &self.image.data[index..index + self.channels]
+    }
+}
+
+fn put(image : &mut Image, x : usize, y : usize, pixel : &[u8])
+{
+    let channels = image.color.channels();
+    let index    = (y * image.width + x) * channels;
+    image.data[index..index + channels].copy_from_slice(pixel);
+}
+
+// ==========================
+// Scale2x / Scale3x
+// ==========================
+
+//  A B C
+//  D E F
+//  G H I
+pub fn scale2x(image : &Image) -> Image
+{
+    let src     = Source::new(image);
+    let mut out = Image::new(image.width * 2, image.height * 2, image.color);
+    for y in 0..image.height as isize
+    {
+        for x in 0..image.width as isize
+        {
+            let (b, d, e, f, h) = (src.get(x, y - 1), src.get(x - 1, y), src.get(x, y), src.get(x + 1, y), src.get(x, y + 1));
+
+            let mut cells = [e; 4];
+            if b != h && d != f
+            {
+                if d == b { cells[0] = d; }
+                if b == f { cells[1] = f; }
+                if d == h { cells[2] = d; }
+                if h == f { cells[3] = f; }
+            }
+
+            let (ox, oy) = (x as usize * 2, y as usize * 2);
+            for (i, cell) in cells.iter().enumerate()
+            {
+                put(&mut out, ox + i % 2, oy + i / 2, cell);
+            }
+        }
+    }
+    out
+}
+
+pub fn scale3x(image : &Image) -> Image
+{
+    let src     = Source::new(image);
+    let mut out = Image::new(image.width * 3, image.height * 3, image.color);
+    for y in 0..image.height as isize
+    {
+        for x in 0..image.width as isize
+        {
+            let a = src.get(x - 1, y - 1);
+            let b = src.get(x,     y - 1);
+            let c = src.get(x + 1, y - 1);
+            let d = src.get(x - 1, y);
+            let e = src.get(x,     y);
+            let f = src.get(x + 1, y);
+            let g = src.get(x - 1, y + 1);
+            let h = src.get(x,     y + 1);
+            let i = src.get(x + 1, y + 1);
+
+            let mut cells = [e; 9];
+            if b != h && d != f
+            {
+                if d == b { cells[0] = d; }
+                if (d == b && e != c) || (b == f && e != a) { cells[1] = b; }
+                if b == f { cells[2] = f; }
+                if (d == b && e != g) || (d == h && e != a) { cells[3] = d; }
+                if (b == f && e != i) || (h == f && e != c) { cells[5] = f; }
+                if d == h { cells[6] = d; }
+                if (d == h && e != i) || (h == f && e != g) { cells[7] = h; }
+                if h == f { cells[8] = f; }
+            }
+
+            let (ox, oy) = (x as usize * 3, y as usize * 3);
+            for (n, cell) in cells.iter().enumerate()
+            {
+                put(&mut out, ox + n % 3, oy + n / 3, cell);
+            }
+        }
+    }
+    out
+}
+
+// ==========================
+// HQ2x
+// ==========================
+
+// A reduced hq2x: instead of the full 256 pattern table, each output
+// quadrant looks at its corner neighbours and blends along diagonal edges
+// using hq2x's YUV similarity thresholds. RGB input only.
+pub fn hq2x(image : &Image) -> Image
+{
+    let src     = Source::new(image);
+    let mut out = Image::new(image.width * 2, image.height * 2, ColorType::RGB);
+    for y in 0..image.height as isize
+    {
+        for x in 0..image.width as isize
+        {
+            let e = src.get(x, y);
+            for quadrant in 0..4
+            {
+                // Direction towards this quadrant's corner.
+                let dx = if quadrant % 2 == 0 { -1 } else { 1 };
+                let dy = if quadrant / 2 == 0 { -1 } else { 1 };
+
+                let corner     = src.get(x + dx, y + dy);
+                let vertical   = src.get(x, y + dy);
+                let horizontal = src.get(x + dx, y);
+
+                let pixel = if similar(vertical, horizontal) && !similar(e, vertical)
+                {
+                    // Diagonal edge through the corner, round it off.
+                    blend(&[(e, 2), (vertical, 3), (horizontal, 3)])
+                }
+                else if !similar(e, corner) && (similar(e, vertical) || similar(e, horizontal))
+                {
+                    blend(&[(e, 3), (corner, 1)])
+                }
+                else if !similar(e, vertical) && !similar(e, horizontal)
+                {
+                    blend(&[(e, 6), (vertical, 1), (horizontal, 1)])
+                }
+                else
+                {
+                    [e[0], e[1], e[2]]
+                };
+
+                put(&mut out, x as usize * 2 + quadrant % 2, y as usize * 2 + quadrant / 2, &pixel);
+            }
+        }
+    }
+    out
+}
+
+fn yuv(pixel : &[u8]) -> [i32; 3]
+{
+    let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+    [(r + g + b) / 3, (r - b) / 4 + 128, (2 * g - r - b) / 8 + 128]
+}
+
+fn similar(a : &[u8], b : &[u8]) -> bool
+{
+    let (a, b) = (yuv(a), yuv(b));
+    (a[0] - b[0]).abs() <= 48 && (a[1] - b[1]).abs() <= 7 && (a[2] - b[2]).abs() <= 6
+}
+
+fn blend(parts : &[(&[u8], u32)]) -> [u8; 3]
+{
+    let total   = parts.iter().map(|(_, weight)| weight).sum::<u32>();
+    let mut out = [0u8; 3];
+    for (channel, value) in out.iter_mut().enumerate()
+    {
+        let sum = parts.iter().map(|(pixel, weight)| pixel[channel] as u32 * weight).sum::<u32>();
+        *value  = ((sum + total / 2) / total) as u8;
+    }
+    out
+}
+
+// ==========================
+// LCD grid
+// ==========================
+
+// Every source pixel becomes a cell whose last row and column are darker,
+// like the gaps between dots on the real screen. RGB input only.
+pub fn lcd_grid(image : &Image, cell : usize) -> Image
+{
+    let mut out = Image::new(image.width * cell, image.height * cell, ColorType::RGB);
+    for y in 0..out.height
+    {
+        for x in 0..out.width
+        {
+            let index = ((y / cell) * image.width + x / cell) * 3;
+            let pixel = &image.data[index..index + 3];
+            let gap   = x % cell == cell - 1 || y % cell == cell - 1;
+            let dim   = |c : u8| -> u8 { if gap { (c as u16 * 3 / 4) as u8 + 0x10 } else { c } };
+            out.put(x, y, [dim(pixel[0]), dim(pixel[1]), dim(pixel[2])]);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn gray(width : usize, data : &[u8]) -> Image
+    {
+        let mut image = Image::new(width, data.len() / width, ColorType::GRAY2);
+        image.data.copy_from_slice(data);
+        image
+    }
+
+    // A diagonal edge gets its corner filled in, flat areas stay blocks.
+    #[test]
+    fn scale2x_smooths_diagonals()
+    {
+        let out = scale2x(&gray(2, &[1, 0,
+                                     1, 1]));
+        assert_eq!(out.data, vec![1, 1, 0, 0,
+                                  1, 1, 1, 0,
+                                  1, 1, 1, 1,
+                                  1, 1, 1, 1]);
+
+        let dot = gray(3, &[0, 0, 0, 0, 2, 0, 0, 0, 0]);
+        assert_eq!(scale2x(&dot).data, dot.scale(2).data);
+    }
+
+    #[test]
+    fn scale3x_smooths_diagonals()
+    {
+        let out = scale3x(&gray(2, &[1, 0,
+                                     1, 1]));
+        assert_eq!(out.data, vec![1, 1, 1, 0, 0, 0,
+                                  1, 1, 1, 1, 0, 0,
+                                  1, 1, 1, 1, 1, 0,
+                                  1, 1, 1, 1, 1, 1,
+                                  1, 1, 1, 1, 1, 1,
+                                  1, 1, 1, 1, 1, 1]);
+
+        let dot = gray(3, &[0, 0, 0, 0, 2, 0, 0, 0, 0]);
+        assert_eq!(scale3x(&dot).data, dot.scale(3).data);
+    }
+}
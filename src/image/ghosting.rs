@@ -0,0 +1,167 @@
+use crate::image::ColorType;
+use crate::image::Image;
+
+// Slow LCD response. Games that flicker sprites on alternate frames for
+// transparency look as intended only with some of the previous frame kept.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Ghosting
+{
+    OFF,
+    // Plain average of this frame and the last.
+    MIX,
+    // Each frame keeps `persistence`/256 of what was on screen before.
+    DECAY(u8)
+}
+
+impl Ghosting
+{
+    // off, mix, decay or decay:<percent kept>
+    pub fn from_name(name : &str) -> Option<Self>
+    {
+        match name.split_once(':')
+        {
+            None => match name
+            {
+                "off"   => Some(Ghosting::OFF),
+                "mix"   => Some(Ghosting::MIX),
+                "decay" => Some(Ghosting::DECAY(128)),
+                _       => None
+            },
+            Some(("decay", percent)) =>
+            {
+                let percent : u32 = percent.parse().ok().filter(|p| *p <= 100)?;
+                Some(Ghosting::DECAY((percent * 255 / 100) as u8))
+            },
+            _ => None
+        }
+    }
+}
+
+// Fed once per emulated frame with the RGB frame. Integer only, so output
+// is identical across runs and machines.
+pub struct FrameBlender
+{
+    mode     : Ghosting,
+    previous : Vec<u8>,
+    // DECAY: screen contents in 8.8 fixed point.
+    screen   : Vec<u16>,
+    output   : Option<Image>
+}
+
+impl FrameBlender
+{
+    pub fn new(mode : Ghosting) -> Self
+    {
+        FrameBlender
+        {
+            mode,
+            previous : Vec::new(),
+            screen   : Vec::new(),
+            output   : None
+        }
+    }
+
+    pub fn mode(&self) -> Ghosting
+    {
+        self.mode
+    }
+
+    pub fn reset(&mut self)
+    {
+        self.previous.clear();
+        self.screen.clear();
+        self.output = None;
+    }
+
+    // Blended frame, None before the first push or with ghosting off.
+    pub fn output(&self) -> Option<&Image>
+    {
+        self.output.as_ref()
+    }
+
+    pub fn push(&mut self, frame : &Image)
+    {
+        if self.mode == Ghosting::OFF
+        {
+            return;
+        }
+
+        let frame = frame.to_rgb();
+        let mut image = Image::new(frame.width, frame.height, ColorType::RGB);
+
+        match self.mode
+        {
+            Ghosting::OFF => {},
+            Ghosting::MIX =>
+            {
+                if self.previous.len() != frame.data.len()
+                {
+                    self.previous = frame.data.clone();
+                }
+                for ((out, &current), &previous) in image.data.iter_mut().zip(&frame.data).zip(&self.previous)
+                {
+                    *out = (current as u16 + previous as u16).div_ceil(2) as u8;
+                }
+                self.previous.copy_from_slice(&frame.data);
+            },
+            Ghosting::DECAY(persistence) =>
+            {
+                if self.screen.len() != frame.data.len()
+                {
+                    self.screen = frame.data.iter().map(|&c| (c as u16) << 8).collect();
+                }
+                let keep = persistence as u32;
+                for ((out, screen), &current) in image.data.iter_mut().zip(self.screen.iter_mut()).zip(&frame.data)
+                {
+                    let value = (*screen as u32 * keep + ((current as u32) << 8) * (256 - keep)) >> 8;
+                    *screen   = value as u16;
+                    *out      = ((value + 0x80) >> 8).min(0xFF) as u8;
+                }
+            }
+        }
+
+        self.output = Some(image);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn solid(value : u8) -> Image
+    {
+        let mut image = Image::new(1, 1, ColorType::RGB);
+        image.data.fill(value);
+        image
+    }
+
+    // First channel of the blended output after each pushed frame.
+    fn run(mode : Ghosting, frames : &[u8]) -> Vec<u8>
+    {
+        let mut blender = FrameBlender::new(mode);
+        frames.iter().map(|&value|
+        {
+            blender.push(&solid(value));
+            blender.output().unwrap().data[0]
+        }).collect()
+    }
+
+    #[test]
+    fn decay_halves_each_frame()
+    {
+        assert_eq!(run(Ghosting::DECAY(128), &[0xFF, 0x00, 0x00, 0x00, 0x00]), vec![0xFF, 0x80, 0x40, 0x20, 0x10]);
+        assert_eq!(run(Ghosting::DECAY(128), &[0x00, 0xFF, 0xFF]), vec![0x00, 0x80, 0xBF]);
+        assert_eq!(run(Ghosting::from_name("decay:0").unwrap(), &[0xFF, 0x00]), vec![0xFF, 0x00]);
+    }
+
+    #[test]
+    fn mix_averages_two_frames()
+    {
+        assert_eq!(run(Ghosting::MIX, &[0xFF, 0x00, 0x00, 0xFF]), vec![0xFF, 0x80, 0x00, 0x80]);
+
+        let mut blender = FrameBlender::new(Ghosting::OFF);
+        blender.push(&solid(0xFF));
+        assert!(blender.output().is_none());
+    }
+}
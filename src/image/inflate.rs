@@ -0,0 +1,295 @@
+// zlib/deflate decoder (RFC 1950/1951), enough to read reference PNGs
+// written by other tools.
+
+const LENGTH_BASE  : [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+                                  35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA : [u8; 29]  = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+                                  3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE    : [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+                                  257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+                                  8193, 12289, 16385, 24577];
+const DIST_EXTRA   : [u8; 30]  = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+                                  7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Order in which code length code lengths are stored.
+const CLEN_ORDER : [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct Bits<'a>
+{
+    data : &'a [u8],
+    pos  : usize,
+    bit  : u32
+}
+
+impl<'a> Bits<'a>
+{
+    fn new(data : &'a [u8]) -> Self
+    {
+        Bits { data, pos : 0, bit : 0 }
+    }
+
+    fn read(&mut self, count : u32) -> Result<u32, String>
+    {
+        let mut value = 0;
+        for i in 0..count
+        {
+            let byte = *self.data.get(self.pos).ok_or("deflate: unexpected end of data")?;
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8
+            {
+                self.bit  = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self)
+    {
+        if self.bit != 0
+        {
+            self.bit  = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+// Canonical Huffman code as counts per length and symbols sorted by code.
+struct Huffman
+{
+    counts  : [u16; 16],
+    symbols : Vec<u16>
+}
+
+impl Huffman
+{
+    fn new(lengths : &[u8]) -> Self
+    {
+        let mut counts = [0u16; 16];
+        for &length in lengths
+        {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for i in 1..16
+        {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate()
+        {
+            if length != 0
+            {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits : &mut Bits) -> Result<u16, String>
+    {
+        let mut code  = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for length in 1..16
+        {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first
+            {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code  <<= 1;
+        }
+        Err("deflate: invalid Huffman code".to_string())
+    }
+}
+
+pub fn zlib_decompress(data : &[u8]) -> Result<Vec<u8>, String>
+{
+    if data.len() < 6 || data[0] & 0x0F != 8 || !((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31)
+    {
+        return Err("zlib: bad header".to_string());
+    }
+    if data[1] & 0x20 != 0
+    {
+        return Err("zlib: preset dictionaries are not supported".to_string());
+    }
+    inflate(&data[2..])
+}
+
+pub fn inflate(data : &[u8]) -> Result<Vec<u8>, String>
+{
+    let mut bits = Bits::new(data);
+    let mut out  = Vec::new();
+
+    loop
+    {
+        let last = bits.read(1)?;
+        match bits.read(2)?
+        {
+            0 => stored_block(&mut bits, &mut out)?,
+            1 =>
+            {
+                let (literals, distances) = fixed_codes();
+                codes_block(&mut bits, &mut out, &literals, &distances)?;
+            },
+            2 =>
+            {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                codes_block(&mut bits, &mut out, &literals, &distances)?;
+            },
+            _ => return Err("deflate: invalid block type".to_string())
+        }
+
+        if last == 1
+        {
+            return Ok(out);
+        }
+    }
+}
+
+fn stored_block(bits : &mut Bits, out : &mut Vec<u8>) -> Result<(), String>
+{
+    bits.align();
+    let header = bits.data.get(bits.pos..bits.pos + 4).ok_or("deflate: unexpected end of data")?;
+    let len    = u16::from_le_bytes([header[0], header[1]]);
+    let nlen   = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen
+    {
+        return Err("deflate: stored block length mismatch".to_string());
+    }
+
+    let start = bits.pos + 4;
+    let block = bits.data.get(start..start + len as usize).ok_or("deflate: unexpected end of data")?;
+    out.extend_from_slice(block);
+    bits.pos = start + len as usize;
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman)
+{
+    let mut lengths = [0u8; 288];
+    lengths[0..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..288].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5u8; 30]))
+}
+
+fn dynamic_codes(bits : &mut Bits) -> Result<(Huffman, Huffman), String>
+{
+    let hlit  = bits.read(5)? as usize + 257;
+    let hdist = bits.read(5)? as usize + 1;
+    let hclen = bits.read(4)? as usize + 4;
+
+    let mut clen_lengths = [0u8; 19];
+    for &index in CLEN_ORDER.iter().take(hclen)
+    {
+        clen_lengths[index] = bits.read(3)? as u8;
+    }
+    let clen = Huffman::new(&clen_lengths);
+
+    let mut lengths = Vec::with_capacity(hlit + hdist);
+    while lengths.len() < hlit + hdist
+    {
+        let (value, repeat) = match clen.decode(bits)?
+        {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 =>
+            {
+                let previous = *lengths.last().ok_or("deflate: repeat without a previous length")?;
+                (previous, 3 + bits.read(2)?)
+            },
+            17 => (0, 3 + bits.read(3)?),
+            _  => (0, 11 + bits.read(7)?)
+        };
+        for _ in 0..repeat
+        {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() != hlit + hdist
+    {
+        return Err("deflate: code lengths overrun".to_string());
+    }
+
+    Ok((Huffman::new(&lengths[..hlit]), Huffman::new(&lengths[hlit..])))
+}
+
+fn codes_block(bits : &mut Bits, out : &mut Vec<u8>, literals : &Huffman, distances : &Huffman) -> Result<(), String>
+{
+    loop
+    {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256
+        {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256
+        {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len()
+        {
+            return Err("deflate: invalid length symbol".to_string());
+        }
+        let length = LENGTH_BASE[index] as usize + bits.read(LENGTH_EXTRA[index] as u32)? as usize;
+
+        let index = distances.decode(bits)? as usize;
+        if index >= DIST_BASE.len()
+        {
+            return Err("deflate: invalid distance symbol".to_string());
+        }
+        let distance = DIST_BASE[index] as usize + bits.read(DIST_EXTRA[index] as u32)? as usize;
+        if distance > out.len()
+        {
+            return Err("deflate: distance too far back".to_string());
+        }
+
+        // Byte by byte, copies may overlap their own output.
+        let start = out.len() - distance;
+        for i in 0..length
+        {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::zlib_decompress;
+
+    #[test]
+    fn fixed_codes()
+    {
+        // zlib.compress(b"abcabcabcabc", 9)
+        let data = [0x78, 0xDA, 0x4B, 0x4C, 0x4A, 0x4E, 0x84, 0x21, 0x00, 0x1D, 0xE0, 0x04, 0x99];
+        assert_eq!(zlib_decompress(&data).unwrap(), b"abcabcabcabc");
+    }
+
+    #[test]
+    fn dynamic_codes()
+    {
+        let expected : Vec<u8> = (0..200u32).map(|i| ((i * i * 7 + i / 3) % 23 + 97) as u8).collect();
+        let data = [0x78, 0xDA, 0xC5, 0xCC, 0x81, 0x0D, 0xC0, 0x20, 0x08, 0x00, 0xB0, 0x5B, 0xD9, 0x44,
+                    0x20, 0x0A, 0x62, 0x50, 0x78, 0x7F, 0x67, 0xAC, 0x07, 0x14, 0xB8, 0x47, 0xFA, 0x03,
+                    0x16, 0x56, 0xB0, 0xCE, 0x6E, 0x78, 0x1B, 0xE8, 0x62, 0xB7, 0x97, 0x40, 0x24, 0xDF,
+                    0x64, 0x2E, 0x84, 0xA9, 0x38, 0xE5, 0x94, 0x57, 0x0E, 0x93, 0xED, 0x34, 0xBA, 0x3B,
+                    0xA9, 0xC4, 0xD5, 0xD8, 0x48, 0xF0, 0x7F, 0xF2, 0x01, 0x12, 0xFD, 0x54, 0x50];
+        assert_eq!(zlib_decompress(&data).unwrap(), expected);
+    }
+}
@@ -0,0 +1,234 @@
+pub mod filter;
+pub mod ghosting;
+pub mod inflate;
+pub mod png;
+pub mod ppm;
+
+use std::path::Path;
+
+use crate::palette::OutputPalette;
+use crate::palette::GRAYSCALE;
+use crate::ppu::SCREEN_HEIGHT;
+use crate::ppu::SCREEN_WIDTH;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ColorType
+{
+    GRAY2,
+    RGB
+}
+
+// How DMG frames are written out: the 2-bit shades as they are, or mapped
+// through the output palette to RGB. CGB frames are always RGB.
+#[derive(Copy, Clone, PartialEq)]
+pub enum DmgOutput
+{
+    SHADES,
+    RGB
+}
+
+// GRAY2 stores one 0-3 level per byte, RGB three bytes per pixel.
+pub struct Image
+{
+    pub width  : usize,
+    pub height : usize,
+    pub color  : ColorType,
+    pub data   : Vec<u8>
+}
+
+impl Image
+{
+    pub fn new(width : usize, height : usize, color : ColorType) -> Self
+    {
+        Image
+        {
+            width,
+            height,
+            color,
+            data : vec![0x0; width * height * color.channels()]
+        }
+    }
+
+    // Shades are stored as gray levels (3 - shade) so that viewers show the
+    // image the right way up while staying lossless.
+    pub fn from_framebuffer(framebuffer : &[u16],
+                            cgb         : bool,
+                            output      : DmgOutput,
+                            palette     : &OutputPalette) -> Self
+    {
+        if !cgb && output == DmgOutput::SHADES
+        {
+            let mut image = Image::new(SCREEN_WIDTH, SCREEN_HEIGHT, ColorType::GRAY2);
+            for (pixel, &shade) in image.data.iter_mut().zip(framebuffer)
+            {
+                *pixel = 3 - (shade as u8 & 0x3);
+            }
+            return image;
+        }
+
+        let mut image = Image::new(SCREEN_WIDTH, SCREEN_HEIGHT, ColorType::RGB);
+        for (pixel, &color) in image.data.chunks_exact_mut(3).zip(framebuffer)
+        {
+            pixel.copy_from_slice(&palette.to_rgb(color, cgb));
+        }
+        image
+    }
+
+    // Nearest neighbour integer upscale.
+    pub fn scale(&self, factor : usize) -> Image
+    {
+        if factor <= 1
+        {
+            return Image { data : self.data.clone(), ..*self };
+        }
+
+        let channels  = self.color.channels();
+        let mut image = Image::new(self.width * factor, self.height * factor, self.color);
+        for y in 0..image.height
+        {
+            for x in 0..image.width
+            {
+                let src = ((y / factor) * self.width + x / factor) * channels;
+                let dst = (y * image.width + x) * channels;
+                image.data[dst..dst + channels].copy_from_slice(&self.data[src..src + channels]);
+            }
+        }
+        image
+    }
+
+    pub fn put(&mut self, x : usize, y : usize, rgb : [u8; 3])
+    {
+        if x < self.width && y < self.height && self.color == ColorType::RGB
+        {
+            let index = (y * self.width + x) * 3;
+            self.data[index..index + 3].copy_from_slice(&rgb);
+        }
+    }
+
+    pub fn fill_rect(&mut self, x : usize, y : usize, width : usize, height : usize, rgb : [u8; 3])
+    {
+        for py in y..y + height
+        {
+            for px in x..x + width
+            {
+                self.put(px, py, rgb);
+            }
+        }
+    }
+
+    // GRAY2 levels become 0x00/0x55/0xAA/0xFF gray.
+    pub fn to_rgb(&self) -> Image
+    {
+        match self.color
+        {
+            ColorType::RGB   => Image { data : self.data.clone(), ..*self },
+            ColorType::GRAY2 =>
+            {
+                let mut image = Image::new(self.width, self.height, ColorType::RGB);
+                for (pixel, &level) in image.data.chunks_exact_mut(3).zip(&self.data)
+                {
+                    pixel.fill((level & 0x3) * 0x55);
+                }
+                image
+            }
+        }
+    }
+
+    // Number of differing pixels, and an image with matching pixels faded
+    // and differing ones in red. Sizes must match.
+    pub fn diff(&self, other : &Image) -> (usize, Image)
+    {
+        let (a, b)     = (self.to_rgb(), other.to_rgb());
+        let mut image  = Image::new(a.width, a.height, ColorType::RGB);
+        let mut count  = 0;
+        let pixels     = a.data.chunks_exact(3).zip(b.data.chunks_exact(3));
+        for (out, (pa, pb)) in image.data.chunks_exact_mut(3).zip(pixels)
+        {
+            if pa == pb
+            {
+                let gray = ((pa[0] as u16 + pa[1] as u16 + pa[2] as u16) / 3) as u8;
+                out.fill(0xC0 + gray / 4);
+            }
+            else
+            {
+                out.copy_from_slice(&[0xFF, 0x00, 0x00]);
+                count += 1;
+            }
+        }
+        (count, image)
+    }
+
+    // FNV-1a over size and RGB data, stable across runs and platforms.
+    pub fn hash(&self) -> u64
+    {
+        let rgb      = self.to_rgb();
+        let mut hash = 0xCBF29CE484222325u64;
+        let size     = [(rgb.width as u32).to_le_bytes(), (rgb.height as u32).to_le_bytes()].concat();
+        for &byte in size.iter().chain(&rgb.data)
+        {
+            hash ^= byte as u64;
+            hash  = hash.wrapping_mul(0x100000001B3);
+        }
+        hash
+    }
+
+    pub fn load(path : &str) -> std::io::Result<Image>
+    {
+        png::read(path)
+    }
+
+    // One pixel wide rectangle outline, clipped to the image.
+    pub fn outline_rect(&mut self, x : i32, y : i32, width : i32, height : i32, rgb : [u8; 3])
+    {
+        let mut put = |px : i32, py : i32|
+        {
+            if px >= 0 && py >= 0
+            {
+                self.put(px as usize, py as usize, rgb);
+            }
+        };
+        for px in x..x + width
+        {
+            put(px, y);
+            put(px, y + height - 1);
+        }
+        for py in y..y + height
+        {
+            put(x, py);
+            put(x + width - 1, py);
+        }
+    }
+
+    // Picks the encoder from the file extension, PNG unless it is .ppm/.pgm.
+    pub fn save(&self, path : &str) -> std::io::Result<()>
+    {
+        let extension = Path::new(path).extension()
+                                       .and_then(|e| e.to_str())
+                                       .unwrap_or("")
+                                       .to_ascii_lowercase();
+        match extension.as_str()
+        {
+            "ppm" => ppm::write_ppm(path, self),
+            "pgm" => ppm::write_pgm(path, self),
+            _     => png::write(path, self)
+        }
+    }
+}
+
+impl ColorType
+{
+    pub fn channels(&self) -> usize
+    {
+        match self
+        {
+            ColorType::GRAY2 => 1,
+            ColorType::RGB   => 3
+        }
+    }
+}
+
+// Plain gray for debug views, ignoring the output palette.
+pub fn dmg_gray(shade : u16) -> [u8; 3]
+{
+    GRAYSCALE[(shade & 0x3) as usize]
+}
@@ -0,0 +1,319 @@
+use std::fs::File;
+use std::io::Write;
+
+use crate::image::inflate;
+use crate::image::ColorType;
+use crate::image::Image;
+
+const SIGNATURE : [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+pub fn write(path : &str, image : &Image) -> std::io::Result<()>
+{
+    let mut file = File::create(path)?;
+    file.write_all(&encode(image))
+}
+
+pub fn encode(image : &Image) -> Vec<u8>
+{
+    let (bit_depth, color_type) = match image.color
+    {
+        ColorType::GRAY2 => (2, 0),
+        ColorType::RGB   => (8, 2)
+    };
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(image.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(image.height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines(image)));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn read(path : &str) -> std::io::Result<Image>
+{
+    let bytes = std::fs::read(path)?;
+    decode(&bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+// Any non-interlaced PNG, converted to RGB. Alpha is dropped and 16-bit
+// samples are cut down to their high byte.
+pub fn decode(png : &[u8]) -> Result<Image, String>
+{
+    if png.len() < 8 || png[0..8] != SIGNATURE
+    {
+        return Err("png: bad signature".to_string());
+    }
+
+    let mut header  = None;
+    let mut palette = Vec::new();
+    let mut idat    = Vec::new();
+
+    let mut pos = 8;
+    while pos + 12 <= png.len()
+    {
+        let len  = u32::from_be_bytes([png[pos], png[pos + 1], png[pos + 2], png[pos + 3]]) as usize;
+        let kind = &png[pos + 4..pos + 8];
+        let data = png.get(pos + 8..pos + 8 + len).ok_or("png: truncated chunk")?;
+        match kind
+        {
+            b"IHDR" if len == 13 =>
+            {
+                let width  = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+                let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+                if data[12] != 0
+                {
+                    return Err("png: interlaced images are not supported".to_string());
+                }
+                header = Some((width, height, data[8], data[9]));
+            },
+            b"PLTE" => palette = data.to_vec(),
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += len + 12;
+    }
+
+    let (width, height, depth, color_type) = header.ok_or("png: missing IHDR")?;
+    let channels = match color_type
+    {
+        0 | 3 => 1,
+        2     => 3,
+        4     => 2,
+        6     => 4,
+        _     => return Err(format!("png: unknown color type {}", color_type))
+    };
+    if !matches!(depth, 1 | 2 | 4 | 8 | 16)
+    {
+        return Err(format!("png: unknown bit depth {}", depth));
+    }
+
+    let bits_per_pixel = channels * depth as usize;
+    let stride         = (width * bits_per_pixel).div_ceil(8);
+    let raw            = inflate::zlib_decompress(&idat)?;
+    if raw.len() < (stride + 1) * height
+    {
+        return Err("png: not enough image data".to_string());
+    }
+    let rows = unfilter(&raw, stride, height, bits_per_pixel.div_ceil(8))?;
+
+    let mut image = Image::new(width, height, ColorType::RGB);
+    for y in 0..height
+    {
+        let row = &rows[y * stride..(y + 1) * stride];
+        for x in 0..width
+        {
+            let sample = |channel : usize| -> u8
+            {
+                match depth
+                {
+                    8  => row[x * channels + channel],
+                    16 => row[(x * channels + channel) * 2],
+                    _  =>
+                    {
+                        let bit   = x * depth as usize;
+                        let shift = 8 - depth as usize - bit % 8;
+                        (row[bit / 8] >> shift) & ((1 << depth) - 1)
+                    }
+                }
+            };
+            // Low bit depths are scaled up to 8 bits, except palette indices.
+            let gray = |value : u8| -> u8
+            {
+                if depth < 8 { (value as u16 * 255 / ((1 << depth) - 1)) as u8 } else { value }
+            };
+
+            let rgb = match color_type
+            {
+                0 | 4 => [gray(sample(0)); 3],
+                3 =>
+                {
+                    let index = sample(0) as usize * 3;
+                    let entry = palette.get(index..index + 3).ok_or("png: palette index out of range")?;
+                    [entry[0], entry[1], entry[2]]
+                },
+                _ => [sample(0), sample(1), sample(2)]
+            };
+            image.put(x, y, rgb);
+        }
+    }
+    Ok(image)
+}
+
+fn unfilter(raw : &[u8], stride : usize, height : usize, bpp : usize) -> Result<Vec<u8>, String>
+{
+    let mut rows = vec![0u8; stride * height];
+    for y in 0..height
+    {
+        let filter = raw[y * (stride + 1)];
+        let line   = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        for x in 0..stride
+        {
+            let a = if x >= bpp { rows[y * stride + x - bpp] } else { 0 };
+            let b = if y > 0 { rows[(y - 1) * stride + x] } else { 0 };
+            let c = if x >= bpp && y > 0 { rows[(y - 1) * stride + x - bpp] } else { 0 };
+            let predictor = match filter
+            {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("png: unknown filter type {}", filter))
+            };
+            rows[y * stride + x] = line[x].wrapping_add(predictor);
+        }
+    }
+    Ok(rows)
+}
+
+fn paeth(a : u8, b : u8, c : u8) -> u8
+{
+    let p  = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+// Raw scanlines, each prefixed with filter type 0.
+fn scanlines(image : &Image) -> Vec<u8>
+{
+    let mut raw = Vec::new();
+    match image.color
+    {
+        ColorType::RGB =>
+        {
+            for row in image.data.chunks_exact(image.width * 3)
+            {
+                raw.push(0);
+                raw.extend_from_slice(row);
+            }
+        },
+        ColorType::GRAY2 =>
+        {
+            for row in image.data.chunks_exact(image.width)
+            {
+                raw.push(0);
+                for pixels in row.chunks(4)
+                {
+                    let mut byte = 0u8;
+                    for (i, level) in pixels.iter().enumerate()
+                    {
+                        byte |= (level & 0x3) << (6 - i * 2);
+                    }
+                    raw.push(byte);
+                }
+            }
+        }
+    }
+    raw
+}
+
+fn write_chunk(png : &mut Vec<u8>, kind : &[u8; 4], data : &[u8])
+{
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// zlib stream made of uncompressed deflate blocks.
+pub fn zlib_stored(data : &[u8]) -> Vec<u8>
+{
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none()
+    {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next()
+    {
+        let last = blocks.peek().is_none() as u8;
+        let len  = block.len() as u16;
+        out.push(last);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub fn crc32(data : &[u8]) -> u32
+{
+    let mut crc = 0xFFFFFFFFu32;
+    for &byte in data
+    {
+        crc ^= byte as u32;
+        for _ in 0..8
+        {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data : &[u8]) -> u32
+{
+    let mut a = 1u32;
+    let mut b = 0u32;
+    for &byte in data
+    {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::crc32;
+    use super::decode;
+    use super::encode;
+    use crate::image::ColorType;
+    use crate::image::Image;
+
+    #[test]
+    fn round_trip()
+    {
+        let mut rgb = Image::new(5, 3, ColorType::RGB);
+        rgb.put(4, 2, [0x12, 0x34, 0x56]);
+        let decoded = decode(&encode(&rgb)).unwrap();
+        assert_eq!(decoded.data, rgb.data);
+
+        // 2-bit gray comes back as RGB.
+        let mut gray = Image::new(5, 3, ColorType::GRAY2);
+        gray.data[7] = 2;
+        let decoded = decode(&encode(&gray)).unwrap();
+        assert_eq!(decoded.data, gray.to_rgb().data);
+    }
+
+    #[test]
+    fn chunks_and_stored_blocks()
+    {
+        // A full CGB frame needs more than one 64 KiB stored block.
+        let mut rgb = Image::new(160, 144, ColorType::RGB);
+        (0..160).for_each(|x| rgb.put(x, x % 144, [x as u8, 0x80, 0xFF - x as u8]));
+        let png = encode(&rgb);
+        assert_eq!(decode(&png).unwrap().data, rgb.data);
+
+        // IHDR first, with its CRC over type and data.
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 160);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 144);
+        assert_eq!(u32::from_be_bytes(png[29..33].try_into().unwrap()), crc32(&png[12..29]));
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
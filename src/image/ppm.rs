@@ -0,0 +1,38 @@
+use std::fs::File;
+use std::io;
+use std::io::Write;
+
+use crate::image::ColorType;
+use crate::image::Image;
+
+// .ppm is always binary PPM (P6), shade images are written as gray.
+pub fn write_ppm(path : &str, image : &Image) -> io::Result<()>
+{
+    let mut file = File::create(path)?;
+    file.write_all(&encode(&image.to_rgb()))
+}
+
+// .pgm is binary PGM (P5) with a maximum value of 3, shade images only.
+pub fn write_pgm(path : &str, image : &Image) -> io::Result<()>
+{
+    if !matches!(image.color, ColorType::GRAY2)
+    {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "PGM holds DMG shades only, use .ppm or .png"));
+    }
+
+    let mut file = File::create(path)?;
+    file.write_all(&encode(image))
+}
+
+pub fn encode(image : &Image) -> Vec<u8>
+{
+    let (magic, max) = match image.color
+    {
+        ColorType::GRAY2 => ("P5", 3),
+        ColorType::RGB   => ("P6", 255)
+    };
+
+    let mut out = format!("{}\n{} {}\n{}\n", magic, image.width, image.height, max).into_bytes();
+    out.extend_from_slice(&image.data);
+    out
+}
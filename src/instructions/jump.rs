@@ -1,4 +1,5 @@
 
+#[allow(clippy::module_inception)]
 pub mod jump
 {
     use crate::bus::*;
@@ -31,10 +32,12 @@ pub mod jump
             if push_pc
             {
                 let sp = ctx.regs.read(Reg::SP);
-                bus::push16(ctx.cart, ctx.mem, ctx.regs, sp);
+                bus::push16(ctx.cart, ctx.mem, ctx.regs, ctx.io, sp);
+                *ctx.cycles += 2;
             }
 
             ctx.regs.write(Reg::PC, addr);
+            *ctx.cycles += 1;
         } 
     }
 
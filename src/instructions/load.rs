@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod load
 {
     use crate::bus::bus;
@@ -16,7 +17,8 @@ pub mod load
                 },
                 _ =>
                 {
-                    bus::write8(ctx.cart, ctx.mem, ctx.regs, ctx.mem_addr, ctx.data as u8); 
+                    bus::write8(ctx.cart, ctx.mem, ctx.regs, ctx.io, ctx.mem_addr, ctx.data as u8); 
+                    *ctx.cycles += 1;
                 }
             }
         }
@@ -34,7 +36,8 @@ pub mod load
         }
         else
         {
-            bus::write8(ctx.cart, ctx.mem, ctx.regs, ctx.mem_addr | 0xFF00, ctx.data as u8);
+            bus::write8(ctx.cart, ctx.mem, ctx.regs, ctx.io, ctx.mem_addr | 0xFF00, ctx.data as u8);
+            *ctx.cycles += 1;
         }
     }
 }
\ No newline at end of file
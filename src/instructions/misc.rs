@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod misc
 {
     use crate::instructions::Context;
@@ -8,4 +9,18 @@ pub mod misc
     {
         *context.int_en = false;
     }
+
+    pub fn halt(context : &mut Context)
+    {
+        *context.halted = true;
+        context.io.hdma.set_paused(true);
+    }
+
+    // STOP is followed by a padding byte. Only the CGB speed switch is
+    // handled, low power mode is not.
+    pub fn stop(context : &mut Context)
+    {
+        context.regs.inc_pc(1);
+        context.io.switch_speed();
+    }
 }
\ No newline at end of file
@@ -9,6 +9,7 @@ use crate::cpu_enums::Reg;
 use crate::cpu_enums::CondType;
 
 use crate::cart::Cart;
+use crate::io::IO;
 use crate::mem::Mem;
 use crate::regs::Regs;
 
@@ -27,10 +28,13 @@ pub struct Context<'a>
     pub mem_addr    : u16,
     pub dest_is_mem : bool,
     pub int_en      : &'a mut bool,
+    pub halted      : &'a mut bool,
+    pub cycles      : &'a mut u8,
 
     pub regs : &'a mut Regs,
     pub cart : &'a mut Cart,
-    pub mem  : &'a mut Mem
+    pub mem  : &'a mut Mem,
+    pub io   : &'a mut IO
 }
 
 #[derive(Copy, Clone)]
@@ -49,6 +53,7 @@ pub struct Mapper
     instructions : [Instruction; 0x100]
 }
 
+#[allow(clippy::too_many_arguments)]
 fn add
 (
     instructions : &mut [Instruction; 0x100], 
@@ -63,17 +68,18 @@ fn add
 {
     instructions[index as usize] = Instruction
     {
-        inst_type : inst_type,
-        addr_mode : addr_mode,
-        reg_1     : reg_1,
-        reg_2     : reg_2,
-        cond_type : cond_type,
-        param     : param
+        inst_type,
+        addr_mode,
+        reg_1,
+        reg_2,
+        cond_type,
+        param
     }
 }
 
 impl Mapper
 {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self
     {
         let default_instruction = Instruction
@@ -89,8 +95,11 @@ impl Mapper
         let mut instructions = [default_instruction; 0x100];
 
         add(&mut instructions, 0x00, InstType::NOP, AddrMode::IMP,   Reg::NONE, Reg::NONE, CondType::NONE, 0);
+        add(&mut instructions, 0x10, InstType::STOP, AddrMode::IMP,  Reg::NONE, Reg::NONE, CondType::NONE, 0);
         add(&mut instructions, 0x31, InstType::LD,  AddrMode::R_D16, Reg::SP,   Reg::NONE, CondType::NONE, 0);
         add(&mut instructions, 0x3E, InstType::LD,  AddrMode::R_D8,  Reg::A,    Reg::NONE, CondType::NONE, 0);
+        add(&mut instructions, 0x40, InstType::LD,  AddrMode::R_R,   Reg::B,    Reg::B,    CondType::NONE, 0);
+        add(&mut instructions, 0x76, InstType::HALT, AddrMode::IMP,  Reg::NONE, Reg::NONE, CondType::NONE, 0);
         add(&mut instructions, 0xC3, InstType::JP,  AddrMode::D16,   Reg::NONE, Reg::NONE, CondType::NONE, 0);
         add(&mut instructions, 0xCD, InstType::CALL, AddrMode::D16,  Reg::NONE, Reg::NONE, CondType::NONE, 0);
         add(&mut instructions, 0xE0, InstType::LDH, AddrMode::A8_R,  Reg::NONE, Reg::A,    CondType::NONE, 0);
@@ -99,7 +108,7 @@ impl Mapper
 
         Mapper
         {
-            instructions : instructions
+            instructions
         }
     }
 
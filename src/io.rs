@@ -0,0 +1,294 @@
+use crate::apu::APU;
+use crate::cpu_enums::Interrupt;
+use crate::dma::DMA;
+use crate::hdma::HDMA;
+use crate::joypad::Joypad;
+use crate::ppu::PpuMode;
+use crate::ppu::PPU;
+use crate::timer::Timer;
+
+// 0xFF00          : P1/JOYP - Joypad
+// 0xFF01 - 0xFF02 : SB, SC - Serial
+// 0xFF04 - 0xFF07 : DIV, TIMA, TMA, TAC - Timer
+// 0xFF0F          : IF - Interrupt Flag
+// 0xFF10 - 0xFF3F : Sound Registers and Wave RAM
+// 0xFF40 - 0xFF4B : LCD Registers
+// 0xFF46          : DMA - OAM DMA Source
+// 0xFF4D          : KEY1 - Speed Switch (CGB)
+// 0xFF4F          : VBK - VRAM Bank (CGB)
+// 0xFF51 - 0xFF55 : HDMA1-HDMA5 - VRAM DMA (CGB)
+// 0xFF56          : RP - Infrared (CGB)
+// 0xFF68 - 0xFF6C : Palettes and OPRI (CGB)
+// 0xFF70          : SVBK - WRAM Bank (CGB)
+// 0xFF72 - 0xFF75 : Undocumented (CGB)
+// 0xFF76 - 0xFF77 : PCM12/PCM34 - Channel Outputs (CGB)
+// Anything else reads 0xFF and ignores writes.
+
+pub struct IO
+{
+    pub ppu  : PPU,
+    pub dma  : DMA,
+    pub hdma : HDMA,
+    pub joypad : Joypad,
+    pub apu  : APU,
+    pub timer : Timer,
+    if_reg   : u8,
+
+    // Registers kept only so they read back. Nothing is connected to the
+    // serial port or the infrared LED, and WRAM is not banked yet.
+    sb       : u8,
+    sc       : u8,
+    rp       : u8,
+    svbk     : u8,
+    undocumented : [u8; 4],
+
+    // T-cycles at normal speed since power on.
+    clock    : u64,
+
+    double_speed : bool,
+    prepare_speed : bool
+}
+
+impl IO
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        IO
+        {
+            ppu    : PPU::new(),
+            dma    : DMA::new(),
+            hdma   : HDMA::new(),
+            joypad : Joypad::new(),
+            apu    : APU::new(),
+            timer  : Timer::new(),
+            if_reg : 0xE1,
+
+            sb     : 0x00,
+            sc     : 0x00,
+            rp     : 0x00,
+            svbk   : 0x00,
+            undocumented : [0x00; 4],
+
+            clock  : 0,
+
+            double_speed : false,
+            prepare_speed : false
+        }
+    }
+
+    // Power-on state for a fresh program, see PPU::reset and APU::reset
+    // for what carries over.
+    pub fn reset(&mut self)
+    {
+        self.ppu.reset();
+        self.apu.reset();
+
+        let ppu = std::mem::replace(&mut self.ppu, PPU::new());
+        let apu = std::mem::replace(&mut self.apu, APU::new());
+        *self = IO { ppu, apu, ..IO::new() };
+    }
+
+    pub fn read8(&self, address : u16) -> u8
+    {
+        match address
+        {
+            0xFF00          => self.joypad.read_reg(),
+            0xFF01          => self.sb,
+            0xFF02          => self.sc | if self.ppu.cgb() { 0x7C } else { 0x7E },
+            0xFF04..=0xFF07 => self.timer.read_reg(address),
+            0xFF0F          => self.if_reg | 0xE0,
+            0xFF10..=0xFF3F => self.apu.read_reg(address),
+            0xFF46          => self.dma.read_reg(),
+            0xFF4D          => self.read_key1(),
+            0xFF40..=0xFF4B |
+            0xFF4F          |
+            0xFF68..=0xFF6C => self.ppu.read_reg(address),
+
+            // CGB only, 0xFF on the DMG.
+            0xFF51..=0xFF55 if self.ppu.cgb() => self.hdma.read_reg(address),
+            0xFF56          if self.ppu.cgb() => 0x3E | (self.rp & 0xC1),
+            0xFF70          if self.ppu.cgb() => 0xF8 | self.svbk,
+            0xFF72..=0xFF74 if self.ppu.cgb() => self.undocumented[(address - 0xFF72) as usize],
+            0xFF75          if self.ppu.cgb() => 0x8F | (self.undocumented[3] & 0x70),
+            0xFF76..=0xFF77 if self.ppu.cgb() => self.apu.read_pcm(address),
+
+            _               => 0xFF
+        }
+    }
+
+    pub fn write8(&mut self, address : u16, value : u8)
+    {
+        match address
+        {
+            0xFF00          => self.joypad.write_reg(value),
+            0xFF01          => self.sb = value,
+            0xFF02          => self.sc = value & if self.ppu.cgb() { 0x83 } else { 0x81 },
+            0xFF04..=0xFF07 =>
+            {
+                let before = self.timer.counter();
+                self.timer.write_reg(address, value);
+                self.step_frame_sequencer(before);
+            },
+            0xFF0F          => self.if_reg = value & 0x1F,
+            0xFF10..=0xFF3F => self.apu.write_reg(address, value),
+            0xFF46          => self.dma.start(value),
+            0xFF4D          => self.prepare_speed = self.ppu.cgb() && value & 0x01 != 0,
+            0xFF51..=0xFF55 if self.ppu.cgb() =>
+            {
+                let in_hblank = self.ppu.mode() == PpuMode::HBLANK && self.ppu.ly() < 144;
+                self.hdma.write_reg(address, value, in_hblank);
+            },
+            0xFF40..=0xFF4B |
+            0xFF4F          |
+            0xFF68..=0xFF6C => self.ppu.write_reg(address, value),
+            0xFF56          => self.rp   = value & 0xC1,
+            0xFF70          => self.svbk = value & 0x07,
+            0xFF72..=0xFF75 => self.undocumented[(address - 0xFF72) as usize] = value,
+            0xFF76..=0xFF77 => {},
+            _               => {}
+        }
+    }
+
+    pub fn request_interrupt(&mut self, interrupt : Interrupt)
+    {
+        self.if_reg |= interrupt as u8;
+    }
+
+    pub fn set_buttons(&mut self, pressed : u8)
+    {
+        if self.joypad.set_pressed(pressed)
+        {
+            self.request_interrupt(Interrupt::JOYPAD);
+        }
+    }
+
+    // Clears the request, returns whether it was pending.
+    pub fn acknowledge(&mut self, interrupt : Interrupt) -> bool
+    {
+        let pending = self.if_reg & interrupt as u8 != 0;
+        self.if_reg &= !(interrupt as u8);
+        pending
+    }
+
+    pub fn interrupt_pending(&self, ie : u8) -> bool
+    {
+        self.if_reg & ie & 0x1F != 0
+    }
+
+    // ==========================
+    // Speed Switch (CGB)
+    // ==========================
+    fn read_key1(&self) -> u8
+    {
+        if !self.ppu.cgb()
+        {
+            return 0xFF;
+        }
+        ((self.double_speed as u8) << 7) | 0x7E | self.prepare_speed as u8
+    }
+
+    pub fn double_speed(&self) -> bool { self.double_speed }
+
+    // Switches speed directly, for callers that skip the STOP sequence.
+    pub fn set_double_speed(&mut self, enabled : bool)
+    {
+        self.double_speed  = enabled;
+        self.prepare_speed = false;
+    }
+    pub fn clock(&self) -> u64 { self.clock }
+
+    // Executed by STOP, returns whether the speed actually changed.
+    pub fn switch_speed(&mut self) -> bool
+    {
+        if !self.prepare_speed
+        {
+            return false;
+        }
+        self.prepare_speed = false;
+        self.double_speed = !self.double_speed;
+        true
+    }
+
+    // ==========================
+    // Divider
+    // ==========================
+
+    // The frame sequencer steps whenever DIV bit 4 falls, bit 5 in double
+    // speed, so writes that reset DIV can step it early.
+    fn step_frame_sequencer(&mut self, before : u16)
+    {
+        let bit = if self.double_speed { 0x2000 } else { 0x1000 };
+        if before & bit != 0 && self.timer.counter() & bit == 0
+        {
+            self.apu.clock_frame_sequencer();
+        }
+    }
+
+    // Advances the I/O devices by a number of M-cycles. The PPU and APU keep
+    // their own pace, so they only see two dots per M-cycle in double speed.
+    pub fn tick(&mut self, cycles : u8)
+    {
+        let dots = if self.double_speed { 2 } else { 4 };
+
+        for _ in 0..cycles
+        {
+            let before = self.timer.counter();
+            if self.timer.tick()
+            {
+                self.request_interrupt(Interrupt::TIMER);
+            }
+            self.step_frame_sequencer(before);
+            self.apu.tick(dots);
+            self.clock += dots as u64;
+
+            for _ in 0..dots
+            {
+                let was_hblank = self.ppu.mode() == PpuMode::HBLANK;
+                self.if_reg |= self.ppu.tick();
+
+                if !was_hblank && self.ppu.mode() == PpuMode::HBLANK
+                {
+                    self.hdma.on_hblank();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::IO;
+
+    // DIV reset, sound powered up at step 0, CH1 one length clock from
+    // running out.
+    fn io_with_short_ch1() -> IO
+    {
+        let mut io = IO::new();
+        for (address, value) in [(0xFF04, 0x00), (0xFF26, 0x00), (0xFF26, 0x80), (0xFF11, 0x3F), (0xFF12, 0xF0), (0xFF14, 0xC0)]
+        {
+            io.write8(address, value);
+        }
+        assert_eq!(io.read8(0xFF26) & 0x01, 0x01);
+        io
+    }
+
+    #[test]
+    fn frame_sequencer_on_div_bit_falling()
+    {
+        // DIV bit 4 falls 2048 M-cycles after a reset.
+        let mut io = io_with_short_ch1();
+        (0..2047).for_each(|_| io.tick(1));
+        assert_eq!(io.read8(0xFF26) & 0x01, 0x01);
+        io.tick(1);
+        assert_eq!(io.read8(0xFF26) & 0x01, 0x00);
+
+        // Resetting DIV with the bit high steps it early.
+        let mut io = io_with_short_ch1();
+        (0..1024).for_each(|_| io.tick(1));
+        assert_eq!(io.read8(0xFF26) & 0x01, 0x01);
+        io.write8(0xFF04, 0x00);
+        assert_eq!(io.read8(0xFF26) & 0x01, 0x00);
+    }
+}
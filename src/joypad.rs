@@ -0,0 +1,70 @@
+// P1/JOYP (0xFF00): bit 4 low selects the d-pad, bit 5 low the buttons,
+// pressed keys read back as 0 in bits 0-3.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Button
+{
+    RIGHT  = 0x01,
+    LEFT   = 0x02,
+    UP     = 0x04,
+    DOWN   = 0x08,
+    A      = 0x10,
+    B      = 0x20,
+    SELECT = 0x40,
+    START  = 0x80
+}
+
+pub struct Joypad
+{
+    select  : u8,
+    // Bit set per Button, d-pad in the low nibble.
+    pressed : u8
+}
+
+impl Joypad
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        Joypad
+        {
+            select  : 0x30,
+            pressed : 0x00
+        }
+    }
+
+    pub fn read_reg(&self) -> u8
+    {
+        0xC0 | self.select | (!self.lines() & 0x0F)
+    }
+
+    pub fn write_reg(&mut self, value : u8)
+    {
+        self.select = value & 0x30;
+    }
+
+    pub fn pressed(&self) -> u8 { self.pressed }
+
+    // Replaces the pressed set, returns true if a selected line went low,
+    // which requests the joypad interrupt.
+    pub fn set_pressed(&mut self, pressed : u8) -> bool
+    {
+        let before   = self.lines();
+        self.pressed = pressed;
+        self.lines() & !before != 0
+    }
+
+    // Selected lines that are currently pulled low, active high.
+    fn lines(&self) -> u8
+    {
+        let mut lines = 0;
+        if self.select & 0x10 == 0
+        {
+            lines |= self.pressed & 0x0F;
+        }
+        if self.select & 0x20 == 0
+        {
+            lines |= self.pressed >> 4;
+        }
+        lines
+    }
+}
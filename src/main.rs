@@ -1,16 +1,206 @@
+pub mod apu;
+pub mod audio;
 pub mod bus;
 pub mod cpu;
 pub mod cpu_enums;
 pub mod cart;
+pub mod cli;
 pub mod console;
+pub mod dma;
+pub mod gbs;
+pub mod hdma;
+pub mod image;
 pub mod instructions;
+pub mod io;
+pub mod joypad;
 pub mod mem;
+pub mod palette;
+pub mod ppu;
 pub mod regs;
+pub mod terminal;
+pub mod timer;
+pub mod video;
+
+#[cfg(test)]
+mod acid;
+#[cfg(test)]
+mod test_support;
 
 pub use console::Console;
 
+use cli::Command;
+use cli::RunOptions;
+
 fn main()
+{
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse(&args, "/workspace/Rust/rust_gbc/roms/dmg-acid2.gb")
+    {
+        Ok(command) => command,
+        Err(e) =>
+        {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match command
+    {
+        Command::START(rom) =>
+        {
+            let mut console = Console::new();
+            console.start(&rom);
+        },
+        Command::RUN(options)  => run(&options),
+        Command::TERM(options) => play_in_terminal(&options)
+    }
+}
+
+fn configure(console : &mut Console, options : &RunOptions)
+{
+    console.set_trace(options.trace);
+    console.set_dmg_palette(options.palette);
+    console.set_color_correction(options.correction);
+    console.set_filter(options.filter);
+    console.set_ghosting(options.ghosting);
+    console.set_renderer(options.renderer);
+    console.set_layers(options.layers);
+    console.set_overlay(options.overlay);
+
+    for channel in &options.mute
+    {
+        console.set_channel_muted(*channel, true);
+    }
+    for channel in &options.solo
+    {
+        console.set_channel_solo(*channel, true);
+    }
+}
+
+// ROMs, or GBS files by their extension.
+fn load(console : &mut Console, options : &RunOptions) -> bool
+{
+    if options.rom.to_ascii_lowercase().ends_with(".gbs")
+    {
+        console.load_gbs(&options.rom, options.track)
+    }
+    else
+    {
+        console.load(&options.rom)
+    }
+}
+
+fn run(options : &RunOptions)
+{
+    let mut console = Console::new();
+    configure(&mut console, options);
+    if !load(&mut console, options)
+    {
+        std::process::exit(1);
+    }
+
+    if let Some(path) = &options.record
+    {
+        if !console.start_recording(path, options.scale, options.record_audio.then_some(options.sample_rate))
+        {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = &options.wav
+    {
+        if !console.start_audio_recording(path, options.sample_rate, options.stems)
+        {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = &options.midi
+    {
+        if !console.start_midi(path)
+        {
+            std::process::exit(1);
+        }
+    }
+
+    if !run_frames(&mut console, options)
+    {
+        std::process::exit(1);
+    }
+
+    if !console.stop_recording() || !console.stop_audio_recording() || !console.stop_midi()
+    {
+        std::process::exit(1);
+    }
+
+    if let Some(path) = &options.screenshot
+    {
+        if !console.save_screenshot(path, options.scale, options.dmg_output)
+        {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(dir) = &options.dump_vram
+    {
+        if !console.dump_vram(dir)
+        {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = &options.dump_audio
+    {
+        if !console.dump_audio(path)
+        {
+            std::process::exit(1);
+        }
+    }
+}
+
+// Runs --frames, starting, looping and stopping the VGM log on the way.
+fn run_frames(console : &mut Console, options : &RunOptions) -> bool
+{
+    let Some(path) = &options.vgm else
+    {
+        console.run_frames(options.frames);
+        return true;
+    };
+
+    let start = options.vgm_start;
+    let stop  = options.vgm_stop.unwrap_or(options.frames);
+
+    console.run_frames(start);
+    if !console.start_vgm_log(path)
+    {
+        return false;
+    }
+
+    match options.vgm_loop
+    {
+        Some(frame) =>
+        {
+            console.run_frames(frame - start);
+            console.mark_vgm_loop();
+            console.run_frames(stop - frame);
+        },
+        None => console.run_frames(stop - start)
+    }
+
+    if !console.stop_vgm_log()
+    {
+        return false;
+    }
+    console.run_frames(options.frames - stop);
+    true
+}
+
+fn play_in_terminal(options : &RunOptions)
 {
     let mut console = Console::new();
-    console.start("/workspace/Rust/rust_gbc/roms/dmg-acid2.gb");
-}
\ No newline at end of file
+    configure(&mut console, options);
+    if !load(&mut console, options) || !terminal::run(&mut console)
+    {
+        std::process::exit(1);
+    }
+}
@@ -8,6 +8,7 @@ pub struct Mem
 
 impl Mem
 {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self
     {
         Mem
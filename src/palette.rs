@@ -0,0 +1,310 @@
+use crate::cart::Cart;
+
+// Converts the PPU's internal colors to RGB. DMG pixels carry their shade
+// in bits 0-1 and the palette they came from in bits 2-3 (BG, OBJ0, OBJ1),
+// CGB pixels are RGB555.
+
+pub const DMG_BG   : u16 = 0;
+pub const DMG_OBJ0 : u16 = 1;
+pub const DMG_OBJ1 : u16 = 2;
+
+pub type Shades = [[u8; 3]; 4];
+
+pub const GRAYSCALE : Shades = [[0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55], [0x00, 0x00, 0x00]];
+pub const GREEN     : Shades = [[0x9B, 0xBC, 0x0F], [0x8B, 0xAC, 0x0F], [0x30, 0x62, 0x30], [0x0F, 0x38, 0x0F]];
+pub const POCKET    : Shades = [[0xC4, 0xCF, 0xA1], [0x8B, 0x95, 0x6D], [0x4D, 0x53, 0x3C], [0x1F, 0x1F, 0x1F]];
+pub const LIGHT     : Shades = [[0x00, 0xB5, 0x81], [0x00, 0x9A, 0x71], [0x00, 0x69, 0x4A], [0x00, 0x4F, 0x3B]];
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum DmgPalette
+{
+    GRAYSCALE,
+    GREEN,
+    POCKET,
+    LIGHT,
+    CUSTOM(Shades),
+    // One of the sets the CGB boot ROM picks with a button combo.
+    COMBO(Combo),
+    // BG, OBJ0 and OBJ1 colors picked like the CGB boot ROM does.
+    COLORIZE
+}
+
+// The palette sets the CGB boot ROM offers for DMG games, named after the
+// button combination that selects them.
+#[derive(Copy, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum Combo
+{
+    RIGHT,
+    LEFT,
+    UP,
+    DOWN,
+    RIGHT_A,
+    LEFT_A,
+    UP_A,
+    DOWN_A,
+    RIGHT_B,
+    LEFT_B,
+    UP_B,
+    DOWN_B
+}
+
+// The CGB boot ROM's DMG colorization data. 30 four color palettes, kept
+// as one RGB555 list since a few combinations start mid-palette.
+const BOOT_COLORS : [u16; 120] =
+[
+    0x7FFF, 0x32BF, 0x00D0, 0x0000,  0x639F, 0x4279, 0x15B0, 0x04CB,
+    0x7FFF, 0x6E31, 0x454A, 0x0000,  0x7FFF, 0x1BEF, 0x0200, 0x0000,
+    0x7FFF, 0x421F, 0x1CF2, 0x0000,  0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000,  0x7FFF, 0x03EF, 0x01D6, 0x0000,
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000,  0x7E74, 0x03FF, 0x0180, 0x0000,
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B,  0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000,  0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0,
+    0x03ED, 0x7FFF, 0x255F, 0x0000,  0x036A, 0x021F, 0x03FF, 0x7FFF,
+    0x7FFF, 0x01DF, 0x0112, 0x0000,  0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000,  0x299F, 0x001A, 0x000C, 0x0000,
+    0x7FFF, 0x027F, 0x001F, 0x0000,  0x7FFF, 0x03E0, 0x0206, 0x0120,
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00,  0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000,  0x03FF, 0x001F, 0x000C, 0x0000,
+    0x7FFF, 0x033F, 0x0193, 0x0000,  0x0000, 0x4200, 0x037F, 0x7FFF,
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000,  0x7FFF, 0x1BEF, 0x6180, 0x0000
+];
+
+// Palette combinations as offsets into BOOT_COLORS: OBJ0, OBJ1, BG.
+const fn comb(obj0 : u8, obj1 : u8, bg : u8) -> [u8; 3] { [obj0 * 4, obj1 * 4, bg * 4] }
+
+const BOOT_COMBINATIONS : [[u8; 3]; 51] =
+[
+    comb( 4,  4, 29), comb(18, 18, 18), comb(20, 20, 20), comb(24, 24, 24),
+    comb( 9,  9,  9), comb( 0,  0,  0), comb(27, 27, 27), comb( 5,  5,  5),
+    comb(12, 12, 12), comb(26, 26, 26), comb(16,  8,  8), comb( 4, 28, 28),
+    comb( 4,  2,  2), comb( 3,  4,  4), comb( 4, 29, 29), comb(28,  4, 28),
+    comb( 2, 17,  2), comb(16, 16,  8), comb( 4,  4,  7), comb( 4,  4, 18),
+    comb( 4,  4, 20), comb(19, 19,  9), [15, 15, 44],     comb(17, 17,  2),
+    comb( 4,  4,  2), comb( 4,  4,  3), comb(28, 28,  0), comb( 3,  3,  0),
+    comb( 0,  0,  1), comb(18, 22, 18), comb(20, 22, 20), comb(24, 22, 24),
+    comb(16, 22,  8), comb(17,  4, 13), [111, 0, 56],     [111, 16, 60],
+    [76, 91, 36],     comb(16, 28, 10), comb( 4, 23, 28), comb(17, 22,  2),
+    comb( 4,  0,  2), comb( 4, 28,  3), comb(28,  3,  0), comb( 3, 28,  4),
+    comb(21, 28,  4), comb( 3, 28,  0), comb(25,  3, 28), comb( 0, 28,  8),
+    comb( 4,  3, 28), comb(28,  3,  6), comb( 4, 28, 29)
+];
+
+// Combination per button combo, in Combo order.
+const COMBO_COMBINATIONS : [u8; 12] = [1, 48, 5, 8, 0, 40, 43, 3, 6, 7, 28, 49];
+
+// Title checksums the boot ROM knows. From FIRST_DUPLICATE on the same
+// checksums repeat, told apart by the 4th title letter in DUPLICATE_LETTERS.
+const TITLE_CHECKSUMS : [u8; 94] =
+[
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3
+];
+const FIRST_DUPLICATE   : usize = 65;
+const DUPLICATE_LETTERS : &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+// Combination per entry of TITLE_CHECKSUMS.
+const TITLE_COMBINATIONS : [u8; 94] =
+[
+     0,  4,  5, 35, 34,  3, 31, 15, 10,  5, 19, 36,  7, 37, 30, 44,
+    21, 32, 31, 20,  5, 33, 13, 14,  5, 29,  5, 18,  9,  3,  2, 26,
+    25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34,
+     5, 42,  6,  5, 33, 25, 42, 42, 40,  2, 16, 25, 42, 42,  5,  0,
+    39, 36, 22, 25,  6, 32, 12, 36, 11, 39, 18, 39, 24, 31, 50, 17,
+    46,  6, 27,  0, 47, 41, 41,  0,  0, 19, 34, 23, 18, 29
+];
+
+// BG, OBJ0 and OBJ1 colors of a boot ROM palette combination.
+fn combination(index : u8) -> [Shades; 3]
+{
+    let [obj0, obj1, bg] = BOOT_COMBINATIONS[index as usize];
+    let shades = |offset : u8| -> Shades
+    {
+        let start = offset as usize;
+        std::array::from_fn(|i| rgb555_to_rgb888(BOOT_COLORS[start + i]))
+    };
+    [shades(bg), shades(obj0), shades(obj1)]
+}
+
+impl Combo
+{
+    // Direction first, then the optional button: "up", "left+a", "down+b".
+    pub fn from_name(name : &str) -> Option<Self>
+    {
+        match name
+        {
+            "right"   => Some(Combo::RIGHT),
+            "left"    => Some(Combo::LEFT),
+            "up"      => Some(Combo::UP),
+            "down"    => Some(Combo::DOWN),
+            "right+a" => Some(Combo::RIGHT_A),
+            "left+a"  => Some(Combo::LEFT_A),
+            "up+a"    => Some(Combo::UP_A),
+            "down+a"  => Some(Combo::DOWN_A),
+            "right+b" => Some(Combo::RIGHT_B),
+            "left+b"  => Some(Combo::LEFT_B),
+            "up+b"    => Some(Combo::UP_B),
+            "down+b"  => Some(Combo::DOWN_B),
+            _         => None
+        }
+    }
+
+    // BG, OBJ0, OBJ1
+    pub fn shades(&self) -> [Shades; 3]
+    {
+        combination(COMBO_COMBINATIONS[*self as usize])
+    }
+}
+
+// Picks the combination like the boot ROM: only Nintendo published games
+// are looked up, everything else and unknown titles get combination 0,
+// the same set as RIGHT_A.
+pub fn colorize(cart : &Cart) -> [Shades; 3]
+{
+    if !cart.has_header()
+    {
+        return combination(0);
+    }
+
+    let nintendo = match cart.old_lic_code()
+    {
+        0x01 => true,
+        0x33 => cart.new_lic_code() == *b"01",
+        _    => false
+    };
+    if !nintendo
+    {
+        return combination(0);
+    }
+
+    let checksum = cart.title_checksum();
+    let fourth   = cart.title()[3];
+
+    let index = TITLE_CHECKSUMS.iter()
+                               .enumerate()
+                               .find(|&(i, &sum)| sum == checksum
+                                               && (i < FIRST_DUPLICATE || DUPLICATE_LETTERS[i - FIRST_DUPLICATE] == fourth))
+                               .map_or(0, |(i, _)| i);
+    combination(TITLE_COMBINATIONS[index])
+}
+
+pub struct OutputPalette
+{
+    dmg        : [Shades; 3],
+    correction : Option<Vec<[u8; 3]>>
+}
+
+impl OutputPalette
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        OutputPalette
+        {
+            dmg        : [GRAYSCALE; 3],
+            correction : None
+        }
+    }
+
+    pub fn set_dmg(&mut self, palette : DmgPalette, cart : &Cart)
+    {
+        self.dmg = match palette
+        {
+            DmgPalette::GRAYSCALE      => [GRAYSCALE; 3],
+            DmgPalette::GREEN          => [GREEN; 3],
+            DmgPalette::POCKET         => [POCKET; 3],
+            DmgPalette::LIGHT          => [LIGHT; 3],
+            DmgPalette::CUSTOM(shades) => [shades; 3],
+            DmgPalette::COMBO(combo)   => combo.shades(),
+            DmgPalette::COLORIZE       => colorize(cart)
+        };
+    }
+
+    // Mimics the CGB LCD: colors are darker and the channels bleed into
+    // each other. The full RGB555 table is built once when enabled.
+    pub fn set_color_correction(&mut self, enabled : bool)
+    {
+        self.correction = if enabled
+        {
+            Some((0..0x8000u16).map(correct_color).collect())
+        }
+        else
+        {
+            None
+        };
+    }
+
+    pub fn color_correction(&self) -> bool { self.correction.is_some() }
+
+    pub fn to_rgb(&self, color : u16, cgb : bool) -> [u8; 3]
+    {
+        if cgb
+        {
+            match &self.correction
+            {
+                Some(table) => table[(color & 0x7FFF) as usize],
+                None        => rgb555_to_rgb888(color)
+            }
+        }
+        else
+        {
+            let palette = ((color >> 2) & 0x3).min(DMG_OBJ1) as usize;
+            self.dmg[palette][(color & 0x3) as usize]
+        }
+    }
+}
+
+pub fn rgb555_to_rgb888(color : u16) -> [u8; 3]
+{
+    let expand = |c : u16| -> u8 { ((c << 3) | (c >> 2)) as u8 };
+    [expand(color & 0x1F), expand((color >> 5) & 0x1F), expand((color >> 10) & 0x1F)]
+}
+
+// LCD gamma of 4.0 mixed down to a 2.2 output gamma.
+fn correct_color(color : u16) -> [u8; 3]
+{
+    let linear = |c : u16| -> f64 { (c as f64 / 31.0).powf(4.0) };
+    let r = linear(color & 0x1F);
+    let g = linear((color >> 5) & 0x1F);
+    let b = linear((color >> 10) & 0x1F);
+
+    let out = |mix : f64| -> u8
+    {
+        ((mix / 255.0).powf(1.0 / 2.2) * (255.0 * 255.0 / 280.0)).round().min(255.0) as u8
+    };
+
+    [
+        out(255.0 * r +  50.0 * g +   0.0 * b),
+        out( 10.0 * r + 230.0 * g +  30.0 * b),
+        out( 50.0 * r +  10.0 * g + 220.0 * b)
+    ]
+}
+
+const fn rgb(colors : [u32; 4]) -> Shades
+{
+    let mut shades = [[0u8; 3]; 4];
+    let mut i = 0;
+    while i < 4
+    {
+        shades[i] = [(colors[i] >> 16) as u8, (colors[i] >> 8) as u8, colors[i] as u8];
+        i += 1;
+    }
+    shades
+}
+
+// "#RRGGBB,#RRGGBB,#RRGGBB,#RRGGBB", lightest shade first.
+pub fn parse_custom(text : &str) -> Option<Shades>
+{
+    let colors : Vec<u32> = text.split(',')
+                                .map(|c| u32::from_str_radix(c.trim().trim_start_matches('#'), 16).ok())
+                                .collect::<Option<Vec<u32>>>()?;
+    if colors.len() != 4 || colors.iter().any(|&c| c > 0xFFFFFF)
+    {
+        return None;
+    }
+    Some(rgb([colors[0], colors[1], colors[2], colors[3]]))
+}
@@ -0,0 +1,359 @@
+use std::collections::VecDeque;
+
+use crate::ppu::*;
+
+// The first tile fetch of a line is thrown away by the hardware.
+const STARTUP_DOTS      : u8 = 6;
+const SPRITE_FETCH_DOTS : u8 = 6;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, PartialEq)]
+enum FetchState
+{
+    TILE,
+    LOW,
+    HIGH,
+    PUSH
+}
+
+#[derive(Copy, Clone)]
+struct BgPixel
+{
+    id   : u8,
+    attr : u8
+}
+
+#[derive(Copy, Clone)]
+struct SpritePixel
+{
+    id    : u8,
+    attr  : u8,
+    index : u8
+}
+
+pub struct Fifo
+{
+    bg  : VecDeque<BgPixel>,
+    obj : VecDeque<SpritePixel>,
+
+    // Fetcher
+    state      : FetchState,
+    step_dots  : u8,
+    tile_x     : u8,
+    tile_index : u8,
+    tile_attr  : u8,
+    tile_lo    : u8,
+    tile_hi    : u8,
+
+    lx           : u8,
+    discard      : u8,
+    startup      : u8,
+    in_window    : bool,
+    // The window put out a pixel this line, so the next one uses the next
+    // window row.
+    window_drawn : bool,
+    // lx of the last WX match, a match only triggers once per position.
+    window_match : Option<u8>,
+    sprites      : Vec<Sprite>,
+    next_sprite  : usize,
+    sprite_dots  : u8
+}
+
+impl Fifo
+{
+    pub fn new() -> Self
+    {
+        Fifo
+        {
+            bg  : VecDeque::with_capacity(16),
+            obj : VecDeque::with_capacity(8),
+
+            state      : FetchState::TILE,
+            step_dots  : 0,
+            tile_x     : 0,
+            tile_index : 0,
+            tile_attr  : 0,
+            tile_lo    : 0,
+            tile_hi    : 0,
+
+            lx           : 0,
+            discard      : 0,
+            startup      : 0,
+            in_window    : false,
+            window_drawn : false,
+            window_match : None,
+            sprites      : Vec::new(),
+            next_sprite  : 0,
+            sprite_dots  : 0
+        }
+    }
+}
+
+impl PPU
+{
+    pub(super) fn fifo_start_line(&mut self)
+    {
+        let mut sprites = self.scan_oam();
+        sprites.sort_by_key(|s| (s.x, s.index));
+
+        let fifo = &mut self.fifo;
+        fifo.bg.clear();
+        fifo.obj.clear();
+        fifo.state        = FetchState::TILE;
+        fifo.step_dots    = 0;
+        fifo.tile_x       = 0;
+        fifo.lx           = 0;
+        fifo.discard      = self.scx % 8;
+        fifo.startup      = STARTUP_DOTS;
+        fifo.in_window    = false;
+        fifo.window_drawn = false;
+        fifo.window_match = None;
+        fifo.sprites      = sprites;
+        fifo.next_sprite  = 0;
+        fifo.sprite_dots  = 0;
+    }
+
+    // Advances the pipeline one dot, returns true once the line is complete.
+    pub(super) fn fifo_step(&mut self) -> bool
+    {
+        if self.fifo.startup > 0
+        {
+            self.fifo.startup -= 1;
+            return false;
+        }
+
+        self.fifo_check_window();
+
+        // A sprite stalls the pipeline until the current BG tile is pushed.
+        let pending = self.fifo_sprite_pending();
+        if self.fifo.sprite_dots > 0 || (pending && !self.fifo.bg.is_empty())
+        {
+            if self.fifo.sprite_dots == 0
+            {
+                self.fifo.sprite_dots = SPRITE_FETCH_DOTS;
+            }
+
+            self.fifo.sprite_dots -= 1;
+            if self.fifo.sprite_dots == 0
+            {
+                self.fifo_load_sprite();
+            }
+            return false;
+        }
+
+        self.fifo_fetch();
+        if pending
+        {
+            return false;
+        }
+
+        self.fifo_shift_out();
+
+        if self.fifo.lx as usize == SCREEN_WIDTH
+        {
+            if self.fifo.window_drawn
+            {
+                self.window_line += 1;
+            }
+            return true;
+        }
+        false
+    }
+
+    // The window starts when lx reaches WX - 7, and again at every later
+    // match after a mid-line WX change. Clearing LCDC.5 hands the rest of the
+    // line back to the BG fetcher.
+    fn fifo_check_window(&mut self)
+    {
+        let enabled = self.bg_enabled()
+                   && self.lcdc & LCDC_WIN_ENABLE != 0
+                   && self.window_triggered
+                   && self.wx <= 166;
+        if !enabled
+        {
+            if self.fifo.in_window
+            {
+                self.fifo_leave_window();
+            }
+            return;
+        }
+
+        let lx = self.fifo.lx;
+        if (self.wx as i16 - 7).max(0) != lx as i16 || self.fifo.window_match == Some(lx)
+        {
+            return;
+        }
+
+        let fifo = &mut self.fifo;
+        fifo.in_window    = true;
+        fifo.window_match = Some(lx);
+        fifo.bg.clear();
+        fifo.state        = FetchState::TILE;
+        fifo.step_dots    = 0;
+        fifo.tile_x       = 0;
+        fifo.discard      = 7u8.saturating_sub(self.wx);
+    }
+
+    // Picks the BG up at the current pixel.
+    fn fifo_leave_window(&mut self)
+    {
+        let x    = self.scx as u16 + self.fifo.lx as u16;
+        let fifo = &mut self.fifo;
+        fifo.in_window = false;
+        fifo.bg.clear();
+        fifo.state     = FetchState::TILE;
+        fifo.step_dots = 0;
+        fifo.tile_x    = ((x / 8) as u8).wrapping_sub(self.scx / 8);
+        fifo.discard   = (x % 8) as u8;
+    }
+
+    fn fifo_sprite_pending(&self) -> bool
+    {
+        self.lcdc & LCDC_OBJ_ENABLE != 0
+            && self.fifo.sprites.get(self.fifo.next_sprite)
+                   .is_some_and(|s| s.x <= self.fifo.lx as i16)
+    }
+
+    fn fifo_load_sprite(&mut self)
+    {
+        let by_index = self.sprite_priority_by_index();
+        let sprite   = &self.fifo.sprites[self.fifo.next_sprite];
+        let (lo, hi) = self.sprite_tile_row(sprite);
+        let attr     = sprite.attr;
+        let index    = sprite.index;
+        let skip     = (self.fifo.lx as i16 - sprite.x) as usize;
+
+        let fifo = &mut self.fifo;
+        while fifo.obj.len() < 8 - skip.min(8)
+        {
+            fifo.obj.push_back(SpritePixel { id : 0, attr : 0, index : 0xFF });
+        }
+
+        // Earlier sprites keep their opaque pixels, which gives DMG X/OAM
+        // priority. In CGB mode a lower OAM index takes the pixel over.
+        for px in skip..8
+        {
+            let bit  = if attr & ATTR_FLIP_X != 0 { px } else { 7 - px };
+            let id   = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+            let slot = &mut fifo.obj[px - skip];
+            if slot.id == 0 || (by_index && id != 0 && index < slot.index)
+            {
+                *slot = SpritePixel { id, attr, index };
+            }
+        }
+
+        fifo.next_sprite += 1;
+    }
+
+    fn fifo_fetch(&mut self)
+    {
+        match self.fifo.state
+        {
+            FetchState::TILE =>
+            {
+                if self.fifo_fetch_step()
+                {
+                    let (map, x, y) = self.fifo_map_position();
+                    let map_addr    = map + (y as u16 / 8) * 32 + x as u16;
+                    self.fifo.tile_index = self.vram_byte(map_addr);
+                    self.fifo.tile_attr  = if self.cgb { self.vram_bank_byte(1, map_addr) } else { 0 };
+                    self.fifo.state      = FetchState::LOW;
+                }
+            },
+            FetchState::LOW =>
+            {
+                if self.fifo_fetch_step()
+                {
+                    self.fifo.tile_lo = self.fifo_tile_row().0;
+                    self.fifo.state   = FetchState::HIGH;
+                }
+            },
+            FetchState::HIGH =>
+            {
+                if self.fifo_fetch_step()
+                {
+                    self.fifo.tile_hi = self.fifo_tile_row().1;
+                    self.fifo.state   = FetchState::PUSH;
+                }
+            },
+            FetchState::PUSH =>
+            {
+                let fifo = &mut self.fifo;
+                if fifo.bg.is_empty()
+                {
+                    let attr = fifo.tile_attr;
+                    for px in 0..8
+                    {
+                        let bit = if attr & ATTR_FLIP_X != 0 { px } else { 7 - px };
+                        let id  = (((fifo.tile_hi >> bit) & 1) << 1) | ((fifo.tile_lo >> bit) & 1);
+                        fifo.bg.push_back(BgPixel { id, attr });
+                    }
+                    fifo.tile_x = fifo.tile_x.wrapping_add(1);
+                    fifo.state  = FetchState::TILE;
+                }
+            }
+        }
+    }
+
+    // Each fetch step takes two dots.
+    fn fifo_fetch_step(&mut self) -> bool
+    {
+        self.fifo.step_dots += 1;
+        if self.fifo.step_dots == 2
+        {
+            self.fifo.step_dots = 0;
+            return true;
+        }
+        false
+    }
+
+    fn fifo_map_position(&self) -> (u16, u8, u8)
+    {
+        if self.fifo.in_window
+        {
+            let map = if self.lcdc & LCDC_WIN_MAP != 0 { 0x9C00 } else { 0x9800 };
+            (map, self.fifo.tile_x & 31, self.window_line)
+        }
+        else
+        {
+            let map = if self.lcdc & LCDC_BG_MAP != 0 { 0x9C00 } else { 0x9800 };
+            (map, (self.scx / 8).wrapping_add(self.fifo.tile_x) & 31, self.ly.wrapping_add(self.scy))
+        }
+    }
+
+    fn fifo_tile_row(&self) -> (u8, u8)
+    {
+        let (_, _, y) = self.fifo_map_position();
+        self.tile_row(self.fifo.tile_index, self.fifo.tile_attr, y % 8)
+    }
+
+    fn fifo_shift_out(&mut self)
+    {
+        let Some(bg) = self.fifo.bg.pop_front() else { return };
+
+        if self.fifo.discard > 0
+        {
+            self.fifo.discard -= 1;
+            return;
+        }
+
+        let bg_id     = if self.bg_enabled() { bg.id } else { 0 };
+        let mut color = self.bg_color(bg_id, bg.attr);
+
+        if let Some(pixel) = self.fifo.obj.pop_front()
+        {
+            let visible = pixel.id != 0
+                       && self.lcdc & LCDC_OBJ_ENABLE != 0
+                       && self.sprite_visible(bg_id, bg.attr, pixel.attr);
+            if visible
+            {
+                color = self.obj_color(pixel.id, pixel.attr);
+            }
+        }
+
+        let index = self.ly as usize * SCREEN_WIDTH + self.fifo.lx as usize;
+        self.framebuffer[index] = color;
+        self.fifo.lx += 1;
+        self.fifo.window_drawn |= self.fifo.in_window;
+    }
+}
@@ -0,0 +1,199 @@
+use crate::image::dmg_gray;
+use crate::image::ColorType;
+use crate::image::Image;
+use crate::palette::rgb555_to_rgb888;
+use crate::ppu::*;
+
+const TILES_PER_BANK : usize = 384;
+const SHEET_COLUMNS  : usize = 16;
+const OAM_COLUMNS    : usize = 8;
+const SWATCH_SIZE    : usize = 16;
+const VIEWPORT_COLOR : [u8; 3] = [0xFF, 0x00, 0x00];
+const GRID_COLOR     : [u8; 3] = [0x40, 0x40, 0x40];
+
+// Debug views of the PPU's source data: tiles, maps, OAM and palettes.
+impl PPU
+{
+    // Screen rectangles (x, y, width, height) of all sprites in OAM,
+    // including off-screen ones, clipped later by whoever draws them.
+    pub fn sprite_bounds(&self) -> Vec<(i32, i32, i32, i32)>
+    {
+        let height = self.sprite_height() as i32;
+        self.oam.chunks_exact(4)
+                .map(|s| (s[1] as i32 - 8, s[0] as i32 - 16, 8, height))
+                .filter(|&(x, y, _, h)| x > -8 && x < SCREEN_WIDTH as i32 && y > -h && y < SCREEN_HEIGHT as i32)
+                .collect()
+    }
+
+    // Top left corner of the window when it is enabled and on screen.
+    pub fn window_origin(&self) -> Option<(i32, i32)>
+    {
+        let enabled = self.bg_enabled() && self.lcdc & LCDC_WIN_ENABLE != 0;
+        let (x, y)  = (self.wx as i32 - 7, self.wy as i32);
+        (enabled && x < SCREEN_WIDTH as i32 && y < SCREEN_HEIGHT as i32).then_some((x, y))
+    }
+
+    // Every tile in 0x8000-0x97FF, 16 per row, with bank 1 below bank 0 on CGB.
+    pub fn tile_sheet(&self) -> Image
+    {
+        let banks     = if self.cgb { 2 } else { 1 };
+        let rows      = TILES_PER_BANK / SHEET_COLUMNS;
+        let mut image = Image::new(SHEET_COLUMNS * 8, rows * 8 * banks, ColorType::RGB);
+
+        for bank in 0..banks
+        {
+            for tile in 0..TILES_PER_BANK
+            {
+                let x = (tile % SHEET_COLUMNS) * 8;
+                let y = (bank * rows + tile / SHEET_COLUMNS) * 8;
+                self.draw_tile(&mut image, x, y, bank as u8, 0x8000 + tile as u16 * 16, 0, |_, id| dmg_gray(id as u16));
+            }
+        }
+        image
+    }
+
+    // A full 256x256 map (0x9800 or 0x9C00) with the SCX/SCY viewport outlined.
+    pub fn tile_map(&self, map : u16) -> Image
+    {
+        let mut image = Image::new(256, 256, ColorType::RGB);
+
+        for y in 0..256usize
+        {
+            for x in 0..256usize
+            {
+                let (id, attr) = self.map_pixel(map, x as u8, y as u8);
+                image.put(x, y, self.to_rgb(self.bg_color(id, attr)));
+            }
+        }
+
+        for i in 0..SCREEN_WIDTH
+        {
+            let x = (self.scx as usize + i) % 256;
+            image.put(x, self.scy as usize, VIEWPORT_COLOR);
+            image.put(x, (self.scy as usize + SCREEN_HEIGHT - 1) % 256, VIEWPORT_COLOR);
+        }
+        for i in 0..SCREEN_HEIGHT
+        {
+            let y = (self.scy as usize + i) % 256;
+            image.put(self.scx as usize, y, VIEWPORT_COLOR);
+            image.put((self.scx as usize + SCREEN_WIDTH - 1) % 256, y, VIEWPORT_COLOR);
+        }
+        image
+    }
+
+    // All 40 sprites with their own palette and flips, 8 per row.
+    pub fn oam_sheet(&self) -> Image
+    {
+        let height    = self.sprite_height() as usize;
+        let cell_w    = 8 + 1;
+        let cell_h    = height + 1;
+        let rows      = 40 / OAM_COLUMNS;
+        let mut image = Image::new(OAM_COLUMNS * cell_w + 1, rows * cell_h + 1, ColorType::RGB);
+        image.fill_rect(0, 0, image.width, image.height, GRID_COLOR);
+
+        for index in 0..40
+        {
+            let base = index * 4;
+            let tile = self.oam[base + 2];
+            let attr = self.oam[base + 3];
+            let x    = (index % OAM_COLUMNS) * cell_w + 1;
+            let y    = (index / OAM_COLUMNS) * cell_h + 1;
+            let bank = if self.cgb && attr & ATTR_BANK != 0 { 1 } else { 0 };
+            let flip = attr & ATTR_FLIP_Y != 0;
+
+            let tiles = if height == 16 { [tile & 0xFE, tile | 0x01] } else { [tile, tile] };
+            for (half, &t) in tiles.iter().take(height / 8).enumerate()
+            {
+                // Vertical flips on 8x16 sprites swap the two tiles as well.
+                let slot = if flip && height == 16 { 1 - half } else { half };
+                self.draw_tile(&mut image, x, y + slot * 8, bank, 0x8000 + t as u16 * 16, attr & (ATTR_FLIP_X | ATTR_FLIP_Y),
+                               |ppu, id| ppu.to_rgb(ppu.obj_color(id, attr)));
+            }
+        }
+        image
+    }
+
+    // One line per OAM entry: position, tile, palette, flips and priority.
+    pub fn oam_listing(&self) -> String
+    {
+        let mut listing = String::from("#   Y    X    Tile Pal Bank FlipX FlipY BG\n");
+        for index in 0..40
+        {
+            let base = index * 4;
+            let attr = self.oam[base + 3];
+            let pal  = if self.cgb { attr & ATTR_PALETTE } else { (attr & ATTR_DMG_PAL != 0) as u8 };
+
+            listing.push_str(&format!
+            (
+                "{:02} {:4} {:4}   {:02X}   {}    {}     {}     {}  {}\n",
+                index,
+                self.oam[base] as i16 - 16,
+                self.oam[base + 1] as i16 - 8,
+                self.oam[base + 2],
+                pal,
+                (attr & ATTR_BANK != 0) as u8,
+                (attr & ATTR_FLIP_X != 0) as u8,
+                (attr & ATTR_FLIP_Y != 0) as u8,
+                (attr & ATTR_PRIORITY != 0) as u8
+            ));
+        }
+        listing
+    }
+
+    // CGB: 8 BG rows then 8 OBJ rows of four colors. DMG: BGP, OBP0, OBP1.
+    pub fn palette_swatches(&self) -> Image
+    {
+        let rows : Vec<[u16; 4]> = if self.cgb
+        {
+            let mut rows = Vec::with_capacity(16);
+            for palettes in [&self.bg_palettes, &self.obj_palettes]
+            {
+                for palette in 0..8
+                {
+                    rows.push([0, 1, 2, 3].map(|id| cgb_color(palettes, palette, id)));
+                }
+            }
+            rows
+        }
+        else
+        {
+            [self.bgp, self.obp0, self.obp1].iter()
+                                            .map(|&p| [0, 1, 2, 3].map(|id| dmg_shade(p, id) as u16))
+                                            .collect()
+        };
+
+        let mut image = Image::new(4 * SWATCH_SIZE, rows.len() * SWATCH_SIZE, ColorType::RGB);
+        for (row, colors) in rows.iter().enumerate()
+        {
+            for (column, &color) in colors.iter().enumerate()
+            {
+                image.fill_rect(column * SWATCH_SIZE, row * SWATCH_SIZE, SWATCH_SIZE, SWATCH_SIZE, self.to_rgb(color));
+            }
+        }
+        image
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_tile<F>(&self, image : &mut Image, x : usize, y : usize, bank : u8, address : u16, flips : u8, color : F)
+        where F : Fn(&PPU, u8) -> [u8; 3]
+    {
+        for row in 0..8u16
+        {
+            let src_row = if flips & ATTR_FLIP_Y != 0 { 7 - row } else { row };
+            let lo      = self.vram_bank_byte(bank, address + src_row * 2);
+            let hi      = self.vram_bank_byte(bank, address + src_row * 2 + 1);
+
+            for px in 0..8u8
+            {
+                let bit = if flips & ATTR_FLIP_X != 0 { px } else { 7 - px };
+                let id  = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+                image.put(x + px as usize, y + row as usize, color(self, id));
+            }
+        }
+    }
+
+    fn to_rgb(&self, color : u16) -> [u8; 3]
+    {
+        if self.cgb { rgb555_to_rgb888(color) } else { dmg_gray(color) }
+    }
+}
@@ -0,0 +1,743 @@
+mod fifo;
+mod inspect;
+mod scanline;
+
+use fifo::Fifo;
+
+use crate::cpu_enums::Interrupt;
+use crate::palette::DMG_OBJ0;
+use crate::palette::DMG_OBJ1;
+
+// LCDC
+pub const LCDC_BG_ENABLE  : u8 = 1 << 0;
+pub const LCDC_OBJ_ENABLE : u8 = 1 << 1;
+pub const LCDC_OBJ_SIZE   : u8 = 1 << 2;
+pub const LCDC_BG_MAP     : u8 = 1 << 3;
+pub const LCDC_TILE_DATA  : u8 = 1 << 4;
+pub const LCDC_WIN_ENABLE : u8 = 1 << 5;
+pub const LCDC_WIN_MAP    : u8 = 1 << 6;
+pub const LCDC_LCD_ENABLE : u8 = 1 << 7;
+
+// STAT
+const STAT_LYC_EQUAL  : u8 = 1 << 2;
+const STAT_HBLANK_INT : u8 = 1 << 3;
+const STAT_VBLANK_INT : u8 = 1 << 4;
+const STAT_OAM_INT    : u8 = 1 << 5;
+const STAT_LYC_INT    : u8 = 1 << 6;
+const STAT_WRITABLE   : u8 = STAT_HBLANK_INT | STAT_VBLANK_INT | STAT_OAM_INT | STAT_LYC_INT;
+
+// BG map attributes (CGB) and sprite attributes
+const ATTR_PALETTE  : u8 = 0x07;
+const ATTR_BANK     : u8 = 1 << 3;
+const ATTR_DMG_PAL  : u8 = 1 << 4;
+const ATTR_FLIP_X   : u8 = 1 << 5;
+const ATTR_FLIP_Y   : u8 = 1 << 6;
+const ATTR_PRIORITY : u8 = 1 << 7;
+
+// Screen
+pub const SCREEN_WIDTH  : usize = 160;
+pub const SCREEN_HEIGHT : usize = 144;
+pub const MAX_SPRITES_PER_LINE : usize = 10;
+
+// Timing
+pub const DOTS_PER_LINE   : u16 = 456;
+pub const LINES_PER_FRAME : u8  = 154;
+pub const VISIBLE_LINES   : u8  = 144;
+pub const DOTS_PER_FRAME  : u32 = DOTS_PER_LINE as u32 * LINES_PER_FRAME as u32;
+const OAM_SCAN_DOTS : u16 = 80;
+const DRAWING_DOTS  : u16 = 172;
+
+// SCANLINE draws a whole line at the end of mode 3, FIFO emulates the
+// pixel pipeline dot by dot and stretches mode 3 accordingly.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Renderer
+{
+    SCANLINE,
+    FIFO
+}
+
+impl Renderer
+{
+    pub fn from_name(name : &str) -> Option<Self>
+    {
+        match name
+        {
+            "scanline" => Some(Renderer::SCANLINE),
+            "fifo"     => Some(Renderer::FIFO),
+            _          => None
+        }
+    }
+}
+
+// Output only switches. Anything other than the default redraws each line
+// after the emulated renderer is done with it, so timing, window line
+// counting and everything else the CPU can see stays untouched.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Layers
+{
+    pub bg           : bool,
+    pub window       : bool,
+    pub sprites      : bool,
+    pub sprite_limit : bool
+}
+
+impl Layers
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        Layers
+        {
+            bg           : true,
+            window       : true,
+            sprites      : true,
+            sprite_limit : true
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum PpuMode
+{
+    HBLANK  = 0,
+    VBLANK  = 1,
+    OAMSCAN = 2,
+    DRAWING = 3
+}
+
+struct Sprite
+{
+    y     : i16,
+    x     : i16,
+    tile  : u8,
+    attr  : u8,
+    index : u8
+}
+
+pub struct PPU
+{
+    vram : [u8; 0x4000],
+    oam  : [u8; 0xA0],
+    cgb  : bool,
+
+    // LCD Registers
+    lcdc : u8,
+    stat : u8,
+    scy  : u8,
+    scx  : u8,
+    ly   : u8,
+    lyc  : u8,
+    bgp  : u8,
+    obp0 : u8,
+    obp1 : u8,
+    wy   : u8,
+    wx   : u8,
+
+    // CGB Registers
+    vbk          : u8,
+    bcps         : u8,
+    ocps         : u8,
+    opri         : u8,
+    bg_palettes  : [u8; 0x40],
+    obj_palettes : [u8; 0x40],
+
+    mode      : PpuMode,
+    line_dots : u16,
+    stat_line : bool,
+    // First line after the LCD is switched on: no OAM scan, mode 0 until
+    // drawing starts.
+    lcd_start : bool,
+    frames    : u64,
+    off_dots  : u32,
+
+    // Renderer
+    renderer         : Renderer,
+    line_renderer    : Renderer,
+    fifo             : Fifo,
+    framebuffer      : Vec<u16>,
+    window_line      : u8,
+    window_triggered : bool,
+    line_window      : u8,
+    layers           : Layers
+}
+
+impl PPU
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        PPU
+        {
+            vram : [0x0; 0x4000],
+            oam  : [0x0; 0xA0],
+            cgb  : false,
+
+            lcdc : 0x91,
+            stat : 0x85,
+            scy  : 0x00,
+            scx  : 0x00,
+            ly   : 0x00,
+            lyc  : 0x00,
+            bgp  : 0xFC,
+            obp0 : 0xFF,
+            obp1 : 0xFF,
+            wy   : 0x00,
+            wx   : 0x00,
+
+            vbk          : 0x00,
+            bcps         : 0x00,
+            ocps         : 0x00,
+            opri         : 0x00,
+            bg_palettes  : [0xFF; 0x40],
+            obj_palettes : [0xFF; 0x40],
+
+            mode      : PpuMode::OAMSCAN,
+            line_dots : 0,
+            stat_line : false,
+            lcd_start : false,
+            frames    : 0,
+            off_dots  : 0,
+
+            renderer         : Renderer::SCANLINE,
+            line_renderer    : Renderer::SCANLINE,
+            fifo             : Fifo::new(),
+            framebuffer      : vec![0x0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            window_line      : 0,
+            window_triggered : false,
+            line_window      : 0,
+            layers           : Layers::new()
+        }
+    }
+
+    pub fn cgb(&self) -> bool { self.cgb }
+    pub fn set_cgb(&mut self, cgb : bool) { self.cgb = cgb; }
+
+    // ==========================
+    // VRAM/OAM
+    // ==========================
+    pub fn read_vram(&self, address : u16) -> u8
+    {
+        if self.vram_locked()
+        {
+            return 0xFF;
+        }
+        self.vram_bank_byte(self.vbk, address)
+    }
+    pub fn write_vram(&mut self, address : u16, value : u8)
+    {
+        if !self.vram_locked()
+        {
+            let index = self.vbk as usize * 0x2000 + (address - 0x8000) as usize;
+            self.vram[index] = value;
+        }
+    }
+
+    pub fn read_vram_unlocked(&self, address : u16) -> u8
+    {
+        self.vram_bank_byte(self.vbk, address)
+    }
+
+    pub fn read_oam(&self, address : u16) -> u8
+    {
+        if self.oam_locked()
+        {
+            return 0xFF;
+        }
+        self.oam[(address - 0xFE00) as usize]
+    }
+    pub fn write_oam(&mut self, address : u16, value : u8)
+    {
+        if !self.oam_locked()
+        {
+            self.oam[(address - 0xFE00) as usize] = value;
+        }
+    }
+
+    // HDMA writes to the selected bank regardless of the PPU mode.
+    pub fn write_vram_dma(&mut self, address : u16, value : u8)
+    {
+        let index = self.vbk as usize * 0x2000 + (address - 0x8000) as usize;
+        self.vram[index] = value;
+    }
+
+    // OAM DMA writes land regardless of the PPU mode.
+    pub fn write_oam_dma(&mut self, index : u8, value : u8)
+    {
+        self.oam[index as usize] = value;
+    }
+
+    fn vram_byte(&self, address : u16) -> u8
+    {
+        self.vram[(address - 0x8000) as usize]
+    }
+    fn vram_bank_byte(&self, bank : u8, address : u16) -> u8
+    {
+        self.vram[bank as usize * 0x2000 + (address - 0x8000) as usize]
+    }
+
+    fn lcd_enabled(&self) -> bool { self.lcdc & LCDC_LCD_ENABLE != 0 }
+    fn vram_locked(&self) -> bool { self.lcd_enabled() && self.mode == PpuMode::DRAWING }
+    fn oam_locked(&self) -> bool
+    {
+        self.lcd_enabled() && (self.mode == PpuMode::OAMSCAN || self.mode == PpuMode::DRAWING)
+    }
+
+    // ==========================
+    // LCD Registers
+    // ==========================
+    pub fn read_reg(&self, address : u16) -> u8
+    {
+        match address
+        {
+            0xFF40 => self.lcdc,
+            0xFF41 => 0x80 | (self.stat & (STAT_WRITABLE | STAT_LYC_EQUAL)) | self.stat_mode(),
+            0xFF42 => self.scy,
+            0xFF43 => self.scx,
+            0xFF44 => self.ly,
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgp,
+            0xFF48 => self.obp0,
+            0xFF49 => self.obp1,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            _ if !self.cgb => 0xFF,
+            0xFF4F => 0xFE | self.vbk,
+            0xFF68 => 0x40 | self.bcps,
+            0xFF69 => self.read_palette(&self.bg_palettes, self.bcps),
+            0xFF6A => 0x40 | self.ocps,
+            0xFF6B => self.read_palette(&self.obj_palettes, self.ocps),
+            0xFF6C => 0xFE | self.opri,
+            _      => 0xFF
+        }
+    }
+    pub fn write_reg(&mut self, address : u16, value : u8)
+    {
+        match address
+        {
+            0xFF40 => self.write_lcdc(value),
+            0xFF41 => self.stat = (self.stat & !STAT_WRITABLE) | (value & STAT_WRITABLE),
+            0xFF42 => self.scy  = value,
+            0xFF43 => self.scx  = value,
+            0xFF44 => {},
+            0xFF45 => self.lyc  = value,
+            0xFF47 => self.bgp  = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy   = value,
+            0xFF4B => self.wx   = value,
+            _ if !self.cgb => {},
+            0xFF4F => self.vbk  = value & 0x01,
+            0xFF68 => self.bcps = value & 0xBF,
+            0xFF69 =>
+            {
+                let locked = self.vram_locked();
+                write_palette(&mut self.bg_palettes, &mut self.bcps, value, locked);
+            },
+            0xFF6A => self.ocps = value & 0xBF,
+            0xFF6B =>
+            {
+                let locked = self.vram_locked();
+                write_palette(&mut self.obj_palettes, &mut self.ocps, value, locked);
+            },
+            0xFF6C => self.opri = value & 0x01,
+            _      => {}
+        }
+    }
+
+    // Palette RAM shares the mode 3 lock with VRAM.
+    fn read_palette(&self, palettes : &[u8; 0x40], spec : u8) -> u8
+    {
+        if self.vram_locked()
+        {
+            return 0xFF;
+        }
+        palettes[(spec & 0x3F) as usize]
+    }
+
+    fn write_lcdc(&mut self, value : u8)
+    {
+        let was_enabled = self.lcd_enabled();
+        self.lcdc = value;
+
+        if was_enabled && !self.lcd_enabled()
+        {
+            self.ly        = 0;
+            self.line_dots = 0;
+            self.mode      = PpuMode::HBLANK;
+            self.stat_line = false;
+            self.lcd_start = false;
+        }
+        else if !was_enabled && self.lcd_enabled()
+        {
+            self.ly               = 0;
+            self.line_dots        = 0;
+            self.mode             = PpuMode::HBLANK;
+            self.lcd_start        = true;
+            self.window_line      = 0;
+            self.window_triggered = false;
+            self.update_lyc();
+        }
+    }
+
+    fn stat_mode(&self) -> u8
+    {
+        if self.lcd_enabled() { self.mode as u8 } else { 0 }
+    }
+
+    pub fn mode(&self) -> PpuMode { self.mode }
+    pub fn renderer(&self) -> Renderer { self.renderer }
+    // Takes effect from the next line.
+    pub fn set_renderer(&mut self, renderer : Renderer) { self.renderer = renderer; }
+
+    // Power-on state. The model, host settings and the frame count stay.
+    pub fn reset(&mut self)
+    {
+        *self = PPU
+        {
+            cgb      : self.cgb,
+            frames   : self.frames,
+            renderer : self.renderer,
+            layers   : self.layers,
+            ..PPU::new()
+        };
+    }
+    pub fn layers(&self) -> Layers { self.layers }
+    pub fn set_layers(&mut self, layers : Layers) { self.layers = layers; }
+    pub fn ly(&self) -> u8 { self.ly }
+    // Keeps counting at the usual rate while the LCD is off, so frame
+    // based loops never stall.
+    pub fn frames(&self) -> u64 { self.frames }
+
+    // One pixel per entry, row major. DMG: shade (0-3) in bits 0-1 and the
+    // source palette in bits 2-3, see palette.rs. CGB: RGB555.
+    pub fn framebuffer(&self) -> &[u16] { &self.framebuffer }
+
+    // ==========================
+    // Timing
+    // ==========================
+    // Advances one dot, returns the interrupts requested (IF bits).
+    pub fn tick(&mut self) -> u8
+    {
+        if !self.lcd_enabled()
+        {
+            self.off_dots += 1;
+            if self.off_dots == DOTS_PER_FRAME
+            {
+                self.off_dots = 0;
+                self.frames  += 1;
+            }
+            return 0;
+        }
+
+        let mut interrupts = 0;
+        self.line_dots += 1;
+
+        match self.mode
+        {
+            PpuMode::OAMSCAN =>
+            {
+                if self.line_dots == OAM_SCAN_DOTS
+                {
+                    self.start_drawing();
+                }
+            },
+            PpuMode::DRAWING =>
+            {
+                let done = match self.line_renderer
+                {
+                    Renderer::SCANLINE => self.line_dots == OAM_SCAN_DOTS + DRAWING_DOTS,
+                    Renderer::FIFO     => self.fifo_step()
+                };
+
+                if done
+                {
+                    if self.line_renderer == Renderer::SCANLINE
+                    {
+                        self.render_scanline();
+                    }
+                    if self.layers != Layers::new()
+                    {
+                        self.draw_line(self.line_window, self.layers);
+                    }
+                    self.mode = PpuMode::HBLANK;
+                }
+            },
+            PpuMode::HBLANK if self.lcd_start =>
+            {
+                if self.line_dots == OAM_SCAN_DOTS
+                {
+                    self.lcd_start = false;
+                    self.start_drawing();
+                }
+            },
+            PpuMode::HBLANK =>
+            {
+                if self.line_dots == DOTS_PER_LINE
+                {
+                    self.next_line();
+                    if self.ly == VISIBLE_LINES
+                    {
+                        self.mode   = PpuMode::VBLANK;
+                        interrupts |= Interrupt::VBLANK as u8;
+                    }
+                    else
+                    {
+                        self.mode = PpuMode::OAMSCAN;
+                    }
+                }
+            },
+            PpuMode::VBLANK =>
+            {
+                if self.line_dots == DOTS_PER_LINE
+                {
+                    self.next_line();
+                    if self.ly == 0
+                    {
+                        self.mode             = PpuMode::OAMSCAN;
+                        self.frames          += 1;
+                        self.window_line      = 0;
+                        self.window_triggered = false;
+                    }
+                }
+            }
+        }
+
+        if self.update_stat_line()
+        {
+            interrupts |= Interrupt::STAT as u8;
+        }
+
+        interrupts
+    }
+
+    fn start_drawing(&mut self)
+    {
+        if self.ly == self.wy
+        {
+            self.window_triggered = true;
+        }
+
+        self.mode          = PpuMode::DRAWING;
+        self.line_renderer = self.renderer;
+        self.line_window   = self.window_line;
+        if self.line_renderer == Renderer::FIFO
+        {
+            self.fifo_start_line();
+        }
+    }
+
+    fn next_line(&mut self)
+    {
+        self.line_dots = 0;
+        self.ly        = (self.ly + 1) % LINES_PER_FRAME;
+        self.update_lyc();
+    }
+
+    fn update_lyc(&mut self)
+    {
+        if self.ly == self.lyc
+        {
+            self.stat |= STAT_LYC_EQUAL;
+        }
+        else
+        {
+            self.stat &= !STAT_LYC_EQUAL;
+        }
+    }
+
+    // STAT interrupts fire on the rising edge of the combined source line.
+    fn update_stat_line(&mut self) -> bool
+    {
+        self.update_lyc();
+
+        let line = (self.stat & STAT_LYC_INT != 0 && self.stat & STAT_LYC_EQUAL != 0)
+                || (self.stat & STAT_HBLANK_INT != 0 && self.mode == PpuMode::HBLANK && !self.lcd_start)
+                || (self.stat & STAT_VBLANK_INT != 0 && self.mode == PpuMode::VBLANK)
+                || (self.stat & STAT_OAM_INT != 0 && self.mode == PpuMode::OAMSCAN);
+
+        let rising     = line && !self.stat_line;
+        self.stat_line = line;
+        rising
+    }
+
+    // ==========================
+    // Tiles/Sprites
+    // ==========================
+    // First ten sprites in OAM order that overlap the current line.
+    fn scan_oam(&self) -> Vec<Sprite>
+    {
+        self.scan_oam_limit(MAX_SPRITES_PER_LINE)
+    }
+
+    fn scan_oam_limit(&self, limit : usize) -> Vec<Sprite>
+    {
+        let height  = self.sprite_height();
+        let ly      = self.ly as i16;
+        let mut sprites = Vec::with_capacity(limit.min(40));
+
+        for index in 0..40
+        {
+            let base = index * 4;
+            let y    = self.oam[base] as i16 - 16;
+            if ly < y || ly >= y + height
+            {
+                continue;
+            }
+
+            sprites.push(Sprite
+            {
+                y,
+                x     : self.oam[base + 1] as i16 - 8,
+                tile  : self.oam[base + 2],
+                attr  : self.oam[base + 3],
+                index : index as u8
+            });
+
+            if sprites.len() == limit
+            {
+                break;
+            }
+        }
+
+        sprites
+    }
+
+    fn sprite_height(&self) -> i16
+    {
+        if self.lcdc & LCDC_OBJ_SIZE != 0 { 16 } else { 8 }
+    }
+
+    fn sprite_tile_row(&self, sprite : &Sprite) -> (u8, u8)
+    {
+        let height  = self.sprite_height();
+        let mut row = self.ly as i16 - sprite.y;
+        if sprite.attr & ATTR_FLIP_Y != 0
+        {
+            row = height - 1 - row;
+        }
+
+        let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+        let bank = if self.cgb && sprite.attr & ATTR_BANK != 0 { 1 } else { 0 };
+        let addr = 0x8000 + tile as u16 * 16 + row as u16 * 2;
+        (self.vram_bank_byte(bank, addr), self.vram_bank_byte(bank, addr + 1))
+    }
+
+    // CGB mode orders sprites by OAM index unless OPRI asks for DMG style.
+    fn sprite_priority_by_index(&self) -> bool
+    {
+        self.cgb && self.opri & 0x01 == 0
+    }
+
+    // Color id and CGB attributes of a BG/window map pixel.
+    fn map_pixel(&self, map : u16, x : u8, y : u8) -> (u8, u8)
+    {
+        let map_addr   = map + (y as u16 / 8) * 32 + (x as u16 / 8);
+        let tile_index = self.vram_byte(map_addr);
+        let attr       = if self.cgb { self.vram_bank_byte(1, map_addr) } else { 0 };
+
+        let (lo, hi) = self.tile_row(tile_index, attr, y % 8);
+        let bit      = if attr & ATTR_FLIP_X != 0 { x % 8 } else { 7 - (x % 8) };
+
+        ((((hi >> bit) & 1) << 1) | ((lo >> bit) & 1), attr)
+    }
+
+    fn tile_row(&self, tile_index : u8, attr : u8, row : u8) -> (u8, u8)
+    {
+        let row  = if attr & ATTR_FLIP_Y != 0 { 7 - row } else { row };
+        let bank = if attr & ATTR_BANK != 0 { 1 } else { 0 };
+        let addr = self.tile_address(tile_index) + row as u16 * 2;
+        (self.vram_bank_byte(bank, addr), self.vram_bank_byte(bank, addr + 1))
+    }
+
+    // ==========================
+    // Colors
+    // ==========================
+    fn bg_color(&self, id : u8, attr : u8) -> u16
+    {
+        if self.cgb
+        {
+            cgb_color(&self.bg_palettes, attr & ATTR_PALETTE, id)
+        }
+        else
+        {
+            dmg_shade(self.bgp, id) as u16
+        }
+    }
+
+    fn obj_color(&self, id : u8, attr : u8) -> u16
+    {
+        if self.cgb
+        {
+            cgb_color(&self.obj_palettes, attr & ATTR_PALETTE, id)
+        }
+        else
+        {
+            let (palette, kind) = if attr & ATTR_DMG_PAL != 0 { (self.obp1, DMG_OBJ1) } else { (self.obp0, DMG_OBJ0) };
+            (kind << 2) | dmg_shade(palette, id) as u16
+        }
+    }
+
+    // Decides between an opaque sprite pixel and the BG/window below it.
+    fn sprite_visible(&self, bg_id : u8, bg_attr : u8, sprite_attr : u8) -> bool
+    {
+        if bg_id == 0
+        {
+            return true;
+        }
+        if self.cgb
+        {
+            // LCDC.0 is the master priority switch in CGB mode.
+            if self.lcdc & LCDC_BG_ENABLE == 0
+            {
+                return true;
+            }
+            if bg_attr & ATTR_PRIORITY != 0
+            {
+                return false;
+            }
+        }
+        sprite_attr & ATTR_PRIORITY == 0
+    }
+
+    // DMG blanks BG and window when LCDC.0 is clear, CGB keeps drawing them.
+    fn bg_enabled(&self) -> bool
+    {
+        self.cgb || self.lcdc & LCDC_BG_ENABLE != 0
+    }
+
+    fn tile_address(&self, tile_index : u8) -> u16
+    {
+        if self.lcdc & LCDC_TILE_DATA != 0
+        {
+            0x8000 + tile_index as u16 * 16
+        }
+        else
+        {
+            (0x9000 + (tile_index as i8 as i32) * 16) as u16
+        }
+    }
+}
+
+fn dmg_shade(palette : u8, id : u8) -> u8
+{
+    (palette >> (id * 2)) & 0x3
+}
+
+fn cgb_color(palettes : &[u8; 0x40], palette : u8, id : u8) -> u16
+{
+    let index = (palette as usize * 4 + id as usize) * 2;
+    (palettes[index] as u16 | (palettes[index + 1] as u16) << 8) & 0x7FFF
+}
+
+// Writes are dropped while locked but the index still auto-increments.
+fn write_palette(palettes : &mut [u8; 0x40], spec : &mut u8, value : u8, locked : bool)
+{
+    if !locked
+    {
+        palettes[(*spec & 0x3F) as usize] = value;
+    }
+    if *spec & 0x80 != 0
+    {
+        *spec = 0x80 | ((*spec + 1) & 0x3F);
+    }
+}
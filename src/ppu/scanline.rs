@@ -0,0 +1,136 @@
+use crate::ppu::*;
+
+impl PPU
+{
+    pub(super) fn render_scanline(&mut self)
+    {
+        if self.draw_line(self.window_line, Layers::new())
+        {
+            self.window_line += 1;
+        }
+    }
+
+    // Draws the current line into the framebuffer using the given window
+    // line, returns whether the window was active. Changes no other state.
+    pub(super) fn draw_line(&mut self, window_line : u8, layers : Layers) -> bool
+    {
+        let mut bg_ids   = [0u8; SCREEN_WIDTH];
+        let mut bg_attrs = [0u8; SCREEN_WIDTH];
+
+        self.render_background(&mut bg_ids, &mut bg_attrs, layers.bg);
+        let window = self.render_window(&mut bg_ids, &mut bg_attrs, window_line, layers.window);
+        if layers.sprites
+        {
+            self.render_sprites(&bg_ids, &bg_attrs, layers.sprite_limit);
+        }
+        window
+    }
+
+    fn render_background(&mut self, bg_ids : &mut [u8; SCREEN_WIDTH], bg_attrs : &mut [u8; SCREEN_WIDTH], shown : bool)
+    {
+        let line = self.ly as usize * SCREEN_WIDTH;
+
+        if !self.bg_enabled() || !shown
+        {
+            let color = self.bg_color(0, 0);
+            self.framebuffer[line..line + SCREEN_WIDTH].fill(color);
+            return;
+        }
+
+        let map = if self.lcdc & LCDC_BG_MAP != 0 { 0x9C00 } else { 0x9800 };
+        let y   = self.ly.wrapping_add(self.scy);
+
+        for x in 0..SCREEN_WIDTH
+        {
+            let bg_x       = (x as u8).wrapping_add(self.scx);
+            let (id, attr) = self.map_pixel(map, bg_x, y);
+
+            bg_ids[x]   = id;
+            bg_attrs[x] = attr;
+            self.framebuffer[line + x] = self.bg_color(id, attr);
+        }
+    }
+
+    // Returns whether the window is active on this line, also when hidden.
+    fn render_window(&mut self,
+                     bg_ids      : &mut [u8; SCREEN_WIDTH],
+                     bg_attrs    : &mut [u8; SCREEN_WIDTH],
+                     window_line : u8,
+                     shown       : bool) -> bool
+    {
+        let visible = self.bg_enabled()
+                   && self.lcdc & LCDC_WIN_ENABLE != 0
+                   && self.window_triggered
+                   && self.wx <= 166;
+        if !visible || !shown
+        {
+            return visible;
+        }
+
+        let line  = self.ly as usize * SCREEN_WIDTH;
+        let map   = if self.lcdc & LCDC_WIN_MAP != 0 { 0x9C00 } else { 0x9800 };
+        let start = self.wx as i16 - 7;
+
+        for x in start.max(0)..SCREEN_WIDTH as i16
+        {
+            let win_x      = (x - start) as u8;
+            let (id, attr) = self.map_pixel(map, win_x, window_line);
+
+            bg_ids[x as usize]   = id;
+            bg_attrs[x as usize] = attr;
+            self.framebuffer[line + x as usize] = self.bg_color(id, attr);
+        }
+
+        true
+    }
+
+    fn render_sprites(&mut self, bg_ids : &[u8; SCREEN_WIDTH], bg_attrs : &[u8; SCREEN_WIDTH], limit : bool)
+    {
+        if self.lcdc & LCDC_OBJ_ENABLE == 0
+        {
+            return;
+        }
+
+        let mut sprites = self.scan_oam_limit(if limit { MAX_SPRITES_PER_LINE } else { 40 });
+
+        // DMG: lower X wins, ties go to the lower OAM index. CGB: OAM index
+        // only. The first opaque pixel claims the dot even when it ends up
+        // hidden behind the BG.
+        if !self.sprite_priority_by_index()
+        {
+            sprites.sort_by_key(|s| (s.x, s.index));
+        }
+
+        let line      = self.ly as usize * SCREEN_WIDTH;
+        let mut drawn = [false; SCREEN_WIDTH];
+
+        for sprite in sprites.iter()
+        {
+            let (lo, hi) = self.sprite_tile_row(sprite);
+
+            for px in 0..8
+            {
+                let x = sprite.x + px;
+                if !(0..SCREEN_WIDTH as i16).contains(&x) || drawn[x as usize]
+                {
+                    continue;
+                }
+
+                let bit = if sprite.attr & ATTR_FLIP_X != 0 { px } else { 7 - px };
+                let id  = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+                if id == 0
+                {
+                    continue;
+                }
+
+                let x = x as usize;
+                drawn[x] = true;
+
+                if self.sprite_visible(bg_ids[x], bg_attrs[x], sprite.attr)
+                {
+                    self.framebuffer[line + x] = self.obj_color(id, sprite.attr);
+                }
+            }
+        }
+    }
+}
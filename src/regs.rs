@@ -11,6 +11,7 @@ pub struct Regs
 
 impl Regs
 {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self
     {
         Regs
@@ -50,7 +51,7 @@ impl Regs
     {
         match reg
         {
-            Reg::NONE => return,
+            Reg::NONE => {},
             Reg::A    => self.write_a(value as u8),
             Reg::F    => self.write_f(value as u8),
             Reg::B    => self.write_b(value as u8),
@@ -180,7 +181,7 @@ impl Regs
     } 
     pub fn write_flag(&mut self, flag : RegF, value : bool)
     {
-        let mut flag_register = self.read_f() as u8;
+        let mut flag_register = self.read_f();
         let bit_position = match flag
         {
             RegF::Z => 0x7,
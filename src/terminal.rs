@@ -0,0 +1,243 @@
+use std::fmt::Write as _;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::console::Console;
+use crate::image::DmgOutput;
+use crate::image::Image;
+use crate::joypad::Button;
+use crate::ppu::DOTS_PER_FRAME;
+use crate::timer::CLOCK_HZ;
+
+// Plays a ROM in a truecolor terminal: two pixels per cell using the upper
+// half block, foreground for the top pixel and background for the bottom.
+
+// Terminals only report key presses, so a key counts as held for this many
+// frames after its last press or auto-repeat.
+const HOLD_FRAMES : u32 = 10;
+
+// Catch up at most this far behind before dropping the backlog.
+const MAX_LAG_FRAMES : u32 = 5;
+
+const HELP : &str = "arrows/WASD d-pad  z/j A  x/k B  enter start  space select  q quit";
+
+#[allow(clippy::upper_case_acronyms)]
+enum Key
+{
+    BUTTON(Button),
+    QUIT
+}
+
+// Raw, non-echoing terminal on the alternate screen, restored on drop so
+// that a panic inside the emulator leaves a usable shell behind.
+struct Screen
+{
+    saved : String
+}
+
+impl Screen
+{
+    fn enter() -> io::Result<Self>
+    {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        stdout.flush()?;
+        Ok(Screen { saved : saved.trim().to_string() })
+    }
+}
+
+impl Drop for Screen
+{
+    fn drop(&mut self)
+    {
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[0m\x1b[?25h\x1b[?1049l").ok();
+        stdout.flush().ok();
+        stty(&[&self.saved]).ok();
+    }
+}
+
+fn stty(args : &[&str]) -> io::Result<String>
+{
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success()
+    {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Runs until q or Ctrl-C. Returns false if the terminal could not be set up.
+pub fn run(console : &mut Console) -> bool
+{
+    let screen = match Screen::enter()
+    {
+        Ok(screen) => screen,
+        Err(e) =>
+        {
+            eprintln!("Failed to set up the terminal: {}", e);
+            return false;
+        }
+    };
+
+    let input      = spawn_input();
+    let frame_time = Duration::from_nanos(DOTS_PER_FRAME as u64 * 1_000_000_000 / CLOCK_HZ as u64);
+    let mut held   = [0u32; 8];
+    let mut next   = Instant::now();
+    let mut out    = String::new();
+
+    'running: loop
+    {
+        for key in read_keys(&input)
+        {
+            match key
+            {
+                Key::QUIT           => break 'running,
+                Key::BUTTON(button) => held[(button as u8).trailing_zeros() as usize] = HOLD_FRAMES
+            }
+        }
+
+        let mut pressed = 0u8;
+        for (bit, frames) in held.iter_mut().enumerate()
+        {
+            if *frames > 0
+            {
+                *frames -= 1;
+                pressed |= 1 << bit;
+            }
+        }
+        console.set_buttons(pressed);
+        console.run_frames(1);
+
+        draw(&console.screenshot(1, DmgOutput::RGB), &mut out);
+        let mut stdout = io::stdout();
+        if stdout.write_all(out.as_bytes()).and_then(|_| stdout.flush()).is_err()
+        {
+            break;
+        }
+
+        next += frame_time;
+        let now = Instant::now();
+        if next > now
+        {
+            thread::sleep(next - now);
+        }
+        else if now - next > frame_time * MAX_LAG_FRAMES
+        {
+            next = now;
+        }
+    }
+
+    drop(screen);
+    true
+}
+
+// Stdin bytes arrive on a separate thread so the frame loop never blocks.
+fn spawn_input() -> Receiver<u8>
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move ||
+    {
+        let mut stdin = io::stdin();
+        let mut byte  = [0u8; 1];
+        while let Ok(1) = stdin.read(&mut byte)
+        {
+            if sender.send(byte[0]).is_err()
+            {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn read_keys(input : &Receiver<u8>) -> Vec<Key>
+{
+    let bytes : Vec<u8> = input.try_iter().collect();
+    let mut keys = Vec::new();
+
+    let mut i = 0;
+    while i < bytes.len()
+    {
+        // Arrow keys: ESC [ A-D, or ESC O A-D in application mode.
+        if bytes[i] == 0x1B && i + 2 < bytes.len() && matches!(bytes[i + 1], b'[' | b'O')
+        {
+            match bytes[i + 2]
+            {
+                b'A' => keys.push(Key::BUTTON(Button::UP)),
+                b'B' => keys.push(Key::BUTTON(Button::DOWN)),
+                b'C' => keys.push(Key::BUTTON(Button::RIGHT)),
+                b'D' => keys.push(Key::BUTTON(Button::LEFT)),
+                _    => {}
+            }
+            i += 3;
+            continue;
+        }
+
+        match bytes[i].to_ascii_lowercase()
+        {
+            b'w'         => keys.push(Key::BUTTON(Button::UP)),
+            b's'         => keys.push(Key::BUTTON(Button::DOWN)),
+            b'a'         => keys.push(Key::BUTTON(Button::LEFT)),
+            b'd'         => keys.push(Key::BUTTON(Button::RIGHT)),
+            b'z' | b'j'  => keys.push(Key::BUTTON(Button::A)),
+            b'x' | b'k'  => keys.push(Key::BUTTON(Button::B)),
+            b'\r' | b'\n' => keys.push(Key::BUTTON(Button::START)),
+            b' '         => keys.push(Key::BUTTON(Button::SELECT)),
+            b'q' | 0x03  => keys.push(Key::QUIT),
+            _            => {}
+        }
+        i += 1;
+    }
+    keys
+}
+
+// Builds the whole frame, setting colors only when they change.
+fn draw(image : &Image, out : &mut String)
+{
+    let pixel = |x : usize, y : usize| -> [u8; 3]
+    {
+        if y >= image.height
+        {
+            return [0, 0, 0];
+        }
+        let index = (y * image.width + x) * 3;
+        [image.data[index], image.data[index + 1], image.data[index + 2]]
+    };
+
+    out.clear();
+    out.push_str("\x1b[H");
+    for y in (0..image.height).step_by(2)
+    {
+        let mut fg = None;
+        let mut bg = None;
+        for x in 0..image.width
+        {
+            let (top, bottom) = (pixel(x, y), pixel(x, y + 1));
+            if fg != Some(top)
+            {
+                write!(out, "\x1b[38;2;{};{};{}m", top[0], top[1], top[2]).ok();
+                fg = Some(top);
+            }
+            if bg != Some(bottom)
+            {
+                write!(out, "\x1b[48;2;{};{};{}m", bottom[0], bottom[1], bottom[2]).ok();
+                bg = Some(bottom);
+            }
+            out.push('\u{2580}');
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+    out.push_str(HELP);
+}
@@ -0,0 +1,24 @@
+// Helpers shared by tests in several modules.
+
+use std::path::PathBuf;
+
+// 32 KiB ROM that starts at 0x150 with the given code, written to the temp
+// directory. Callers remove it when done.
+pub fn test_rom(name : &str, code : &[u8]) -> PathBuf
+{
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + code.len()].copy_from_slice(code);
+
+    let path = std::env::temp_dir().join(format!("rust_gbc-{}-{}.gb", name, std::process::id()));
+    std::fs::write(&path, rom).expect("write test rom");
+    path
+}
+
+// Path in the temp directory for a file a test writes. Callers remove it.
+pub fn temp_path(name : &str) -> String
+{
+    std::env::temp_dir().join(format!("rust_gbc-{}-{}", std::process::id(), name))
+                        .to_string_lossy()
+                        .into_owned()
+}
@@ -0,0 +1,367 @@
+// DIV, TIMA, TMA and TAC (0xFF04-0xFF07). Everything hangs off a 16-bit
+// system counter that advances 4 per M-cycle, DIV is its upper byte. TIMA
+// counts falling edges of the TAC-selected counter bit ANDed with the
+// enable bit, so DIV and TAC writes that drop that signal count as well.
+pub struct Timer
+{
+    counter  : u16,
+    tima     : u8,
+    tma      : u8,
+    tac      : u8,
+
+    // TIMA overflowed last M-cycle, it reads 0 until TMA is loaded.
+    overflow : bool,
+    // TMA was loaded into TIMA this M-cycle, TIMA writes are ignored and
+    // TMA writes go straight through.
+    reloaded : bool
+}
+
+// Master clock, T-cycles per second at normal speed. Everything else is
+// derived from it, the system counter included.
+pub const CLOCK_HZ : u32 = 4_194_304;
+
+// Counter bit per TAC clock select: 4096, 262144, 65536 and 16384 Hz.
+const TAC_BITS : [u16; 4] = [0x0200, 0x0008, 0x0020, 0x0080];
+
+// System counter as the boot ROM hands over, it runs a different length
+// on each model.
+const POST_BOOT_DMG : u16 = 0xABCC;
+const POST_BOOT_CGB : u16 = 0x1EA0;
+
+impl Timer
+{
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self
+    {
+        Timer
+        {
+            counter  : POST_BOOT_DMG,
+            tima     : 0,
+            tma      : 0,
+            tac      : 0,
+
+            overflow : false,
+            reloaded : false
+        }
+    }
+
+    // Power-on counter for the model, only meaningful before the first tick.
+    pub fn set_cgb(&mut self, cgb : bool)
+    {
+        self.counter = if cgb { POST_BOOT_CGB } else { POST_BOOT_DMG };
+    }
+
+    pub fn counter(&self) -> u16 { self.counter }
+
+    pub fn read_reg(&self, address : u16) -> u8
+    {
+        match address
+        {
+            0xFF04 => (self.counter >> 8) as u8,
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            _      => self.tac | 0xF8
+        }
+    }
+
+    pub fn write_reg(&mut self, address : u16, value : u8)
+    {
+        match address
+        {
+            0xFF04 =>
+            {
+                let before   = self.signal();
+                self.counter = 0;
+                if before
+                {
+                    self.increment();
+                }
+            },
+            0xFF05 =>
+            {
+                // Writing during the delay cancels the reload and interrupt.
+                if !self.reloaded
+                {
+                    self.tima     = value;
+                    self.overflow = false;
+                }
+            },
+            0xFF06 =>
+            {
+                self.tma = value;
+                if self.reloaded
+                {
+                    self.tima = value;
+                }
+            },
+            _ =>
+            {
+                let before = self.signal();
+                self.tac   = value & 0x07;
+                if before && !self.signal()
+                {
+                    self.increment();
+                }
+            }
+        }
+    }
+
+    // Advances one M-cycle, returns true when the timer interrupt is
+    // requested.
+    pub fn tick(&mut self) -> bool
+    {
+        self.reloaded = false;
+
+        let mut interrupt = false;
+        if self.overflow
+        {
+            self.overflow = false;
+            self.reloaded = true;
+            self.tima     = self.tma;
+            interrupt     = true;
+        }
+
+        let before   = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        if before && !self.signal()
+        {
+            self.increment();
+        }
+
+        interrupt
+    }
+
+    fn signal(&self) -> bool
+    {
+        self.tac & 0x04 != 0 && self.counter & TAC_BITS[(self.tac & 0x03) as usize] != 0
+    }
+
+    fn increment(&mut self)
+    {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima     = tima;
+        self.overflow = overflow;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::Timer;
+
+    // 262144 Hz: TIMA counts every 4 M-cycles.
+    fn fast_timer(tima : u8) -> Timer
+    {
+        let mut timer = Timer::new();
+        timer.write_reg(0xFF04, 0x00);
+        timer.write_reg(0xFF07, 0x05);
+        timer.write_reg(0xFF05, tima);
+        timer.write_reg(0xFF06, 0x80);
+        timer
+    }
+
+    #[test]
+    fn overflow_reloads_one_cycle_late()
+    {
+        let mut timer = fast_timer(0xFF);
+        let mut cycles = 0;
+        while timer.read_reg(0xFF05) == 0xFF
+        {
+            assert!(!timer.tick());
+            cycles += 1;
+        }
+        assert_eq!(cycles, 4);
+        assert_eq!(timer.read_reg(0xFF05), 0x00);
+
+        assert!(timer.tick());
+        assert_eq!(timer.read_reg(0xFF05), 0x80);
+    }
+
+    #[test]
+    fn writes_around_reload()
+    {
+        // TIMA written during the delay: no reload, no interrupt.
+        let mut timer = fast_timer(0xFF);
+        (0..4).for_each(|_| { timer.tick(); });
+        timer.write_reg(0xFF05, 0x10);
+        assert!(!timer.tick());
+        assert_eq!(timer.read_reg(0xFF05), 0x10);
+
+        // TIMA written as TMA is loaded is ignored, TMA goes through.
+        let mut timer = fast_timer(0xFF);
+        (0..5).for_each(|_| { timer.tick(); });
+        timer.write_reg(0xFF05, 0x10);
+        assert_eq!(timer.read_reg(0xFF05), 0x80);
+        timer.write_reg(0xFF06, 0x20);
+        assert_eq!(timer.read_reg(0xFF05), 0x20);
+    }
+
+    #[test]
+    fn div_and_tac_writes_glitch()
+    {
+        // Resetting DIV with the selected bit high is a falling edge.
+        let mut timer = fast_timer(0x00);
+        (0..2).for_each(|_| { timer.tick(); });
+        timer.write_reg(0xFF04, 0x00);
+        assert_eq!(timer.read_reg(0xFF04), 0x00);
+        assert_eq!(timer.read_reg(0xFF05), 0x01);
+
+        // So is disabling the timer while the bit is high.
+        let mut timer = fast_timer(0x00);
+        (0..2).for_each(|_| { timer.tick(); });
+        timer.write_reg(0xFF07, 0x00);
+        assert_eq!(timer.read_reg(0xFF05), 0x01);
+        assert_eq!(timer.read_reg(0xFF07), 0xF8);
+    }
+
+    // ==========================
+    // Mooneye acceptance/timer cases
+    // ==========================
+
+    // Runs M-cycles, returns how many interrupts were requested.
+    fn run(timer : &mut Timer, cycles : usize) -> usize
+    {
+        (0..cycles).filter(|_| timer.tick()).count()
+    }
+
+    #[test]
+    fn div_write()
+    {
+        let mut timer = Timer::new();
+        run(&mut timer, 1000);
+        timer.write_reg(0xFF04, 0x5A);
+        assert_eq!(timer.read_reg(0xFF04), 0x00);
+        run(&mut timer, 63);
+        assert_eq!(timer.read_reg(0xFF04), 0x00);
+        run(&mut timer, 1);
+        assert_eq!(timer.read_reg(0xFF04), 0x01);
+    }
+
+    // tim00, tim01, tim10, tim11 and their div_trigger variants. TAC value
+    // and M-cycles per TIMA increment.
+    const RATES : [(u8, usize); 4] = [(0x04, 256), (0x05, 4), (0x06, 16), (0x07, 64)];
+
+    #[test]
+    fn tac_rates()
+    {
+        for (tac, period) in RATES
+        {
+            let mut timer = Timer::new();
+            timer.write_reg(0xFF04, 0x00);
+            timer.write_reg(0xFF07, tac);
+            timer.write_reg(0xFF05, 0x00);
+
+            run(&mut timer, period - 1);
+            assert_eq!(timer.read_reg(0xFF05), 0x00, "TAC {:02X}", tac);
+            run(&mut timer, 1);
+            assert_eq!(timer.read_reg(0xFF05), 0x01, "TAC {:02X}", tac);
+            run(&mut timer, period * 9);
+            assert_eq!(timer.read_reg(0xFF05), 0x0A, "TAC {:02X}", tac);
+        }
+    }
+
+    #[test]
+    fn tac_rates_div_trigger()
+    {
+        // Resetting DIV in the second half of a period counts once.
+        for (tac, period) in RATES
+        {
+            for (cycles, expected) in [(period / 2 - 1, 0x00), (period / 2, 0x01)]
+            {
+                let mut timer = Timer::new();
+                timer.write_reg(0xFF04, 0x00);
+                timer.write_reg(0xFF07, tac);
+                timer.write_reg(0xFF05, 0x00);
+                run(&mut timer, cycles);
+                timer.write_reg(0xFF04, 0x00);
+                assert_eq!(timer.read_reg(0xFF05), expected, "TAC {:02X} after {}", tac, cycles);
+            }
+        }
+    }
+
+    #[test]
+    fn rapid_toggle()
+    {
+        // Turning the timer off with the selected bit low does nothing.
+        let mut timer = fast_timer(0x00);
+        run(&mut timer, 1);
+        for _ in 0..3
+        {
+            timer.write_reg(0xFF07, 0x01);
+            timer.write_reg(0xFF07, 0x05);
+        }
+        assert_eq!(timer.read_reg(0xFF05), 0x00);
+
+        // With it high every off counts, turning back on does not.
+        run(&mut timer, 1);
+        for _ in 0..3
+        {
+            timer.write_reg(0xFF07, 0x01);
+            timer.write_reg(0xFF07, 0x05);
+        }
+        assert_eq!(timer.read_reg(0xFF05), 0x03);
+
+        // The regular edge still follows.
+        run(&mut timer, 2);
+        assert_eq!(timer.read_reg(0xFF05), 0x04);
+    }
+
+    #[test]
+    fn tima_reload()
+    {
+        // Increments land on M-cycles 4, 8, 12...
+        let mut timer = fast_timer(0xFE);
+        assert_eq!(run(&mut timer, 8), 0);
+        assert_eq!(timer.read_reg(0xFF05), 0x00);
+        assert_eq!(run(&mut timer, 1), 1);
+        assert_eq!(timer.read_reg(0xFF05), 0x80);
+        assert_eq!(run(&mut timer, 3), 0);
+        assert_eq!(timer.read_reg(0xFF05), 0x81);
+    }
+
+    #[test]
+    fn tima_write_reloading()
+    {
+        // During the delay: the write wins, no reload, no interrupt.
+        let mut timer = fast_timer(0xFF);
+        run(&mut timer, 4);
+        timer.write_reg(0xFF05, 0x10);
+        assert_eq!(run(&mut timer, 1), 0);
+        assert_eq!(timer.read_reg(0xFF05), 0x10);
+
+        // On the reload cycle: ignored.
+        let mut timer = fast_timer(0xFF);
+        assert_eq!(run(&mut timer, 5), 1);
+        timer.write_reg(0xFF05, 0x10);
+        assert_eq!(timer.read_reg(0xFF05), 0x80);
+
+        // One cycle later it sticks again.
+        run(&mut timer, 1);
+        timer.write_reg(0xFF05, 0x10);
+        assert_eq!(timer.read_reg(0xFF05), 0x10);
+    }
+
+    #[test]
+    fn tma_write_reloading()
+    {
+        // During the delay: the new TMA is what gets loaded.
+        let mut timer = fast_timer(0xFF);
+        run(&mut timer, 4);
+        timer.write_reg(0xFF06, 0x40);
+        assert_eq!(run(&mut timer, 1), 1);
+        assert_eq!(timer.read_reg(0xFF05), 0x40);
+
+        // On the reload cycle: TIMA follows TMA.
+        let mut timer = fast_timer(0xFF);
+        run(&mut timer, 5);
+        timer.write_reg(0xFF06, 0x40);
+        assert_eq!(timer.read_reg(0xFF05), 0x40);
+
+        // One cycle later only TMA changes.
+        let mut timer = fast_timer(0xFF);
+        run(&mut timer, 6);
+        timer.write_reg(0xFF06, 0x40);
+        assert_eq!(timer.read_reg(0xFF05), 0x80);
+        assert_eq!(timer.read_reg(0xFF06), 0x40);
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+
+use crate::image::Image;
+use crate::video::frame_centiseconds;
+
+// Most viewers treat delays below 2/100 s as 1/10 s, so frames that would
+// be shown for less are dropped instead. Overall timing stays exact.
+const MIN_DELAY : u64 = 2;
+
+const MAX_CODE_SIZE : u32 = 12;
+
+// Looping animated GIF, one local color table per frame.
+pub struct Writer
+{
+    out     : BufWriter<File>,
+    started : bool,
+    pending : Option<Image>,
+    frames  : u64,
+    // Time up to which frames have been written, in 1/100 s.
+    written : u64
+}
+
+impl Writer
+{
+    pub fn create(path : &str) -> io::Result<Self>
+    {
+        Ok(Writer
+        {
+            out     : BufWriter::new(File::create(path)?),
+            started : false,
+            pending : None,
+            frames  : 0,
+            written : 0
+        })
+    }
+
+    pub fn push(&mut self, image : &Image) -> io::Result<()>
+    {
+        if !self.started
+        {
+            self.write_header(image.width, image.height)?;
+            self.started = true;
+        }
+
+        let now = frame_centiseconds(self.frames);
+        self.frames += 1;
+
+        match self.pending.take()
+        {
+            None => self.pending = Some(image.to_rgb()),
+            Some(pending) =>
+            {
+                let delay = now - self.written;
+                if delay >= MIN_DELAY
+                {
+                    self.write_frame(&pending, delay)?;
+                    self.written = now;
+                    self.pending = Some(image.to_rgb());
+                }
+                else
+                {
+                    self.pending = Some(pending);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()>
+    {
+        if let Some(pending) = self.pending.take()
+        {
+            let delay = (frame_centiseconds(self.frames) - self.written).max(MIN_DELAY);
+            self.write_frame(&pending, delay)?;
+        }
+        if self.started
+        {
+            self.out.write_all(&[0x3B])?;
+        }
+        self.out.flush()
+    }
+
+    fn write_header(&mut self, width : usize, height : usize) -> io::Result<()>
+    {
+        self.out.write_all(b"GIF89a")?;
+        self.out.write_all(&(width as u16).to_le_bytes())?;
+        self.out.write_all(&(height as u16).to_le_bytes())?;
+        // No global color table, background 0, square pixels.
+        self.out.write_all(&[0x00, 0x00, 0x00])?;
+
+        // Loop forever.
+        self.out.write_all(&[0x21, 0xFF, 0x0B])?;
+        self.out.write_all(b"NETSCAPE2.0")?;
+        self.out.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])
+    }
+
+    fn write_frame(&mut self, image : &Image, delay : u64) -> io::Result<()>
+    {
+        let (palette, indices) = quantize(image);
+
+        // Smallest power of two table that fits, at least 2 entries.
+        let mut table_bits = 1;
+        while (1 << table_bits) < palette.len()
+        {
+            table_bits += 1;
+        }
+
+        // Graphic control extension.
+        let delay = delay.min(0xFFFF) as u16;
+        self.out.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        self.out.write_all(&delay.to_le_bytes())?;
+        self.out.write_all(&[0x00, 0x00])?;
+
+        // Image descriptor with a local color table.
+        self.out.write_all(&[0x2C, 0x00, 0x00, 0x00, 0x00])?;
+        self.out.write_all(&(image.width as u16).to_le_bytes())?;
+        self.out.write_all(&(image.height as u16).to_le_bytes())?;
+        self.out.write_all(&[0x80 | (table_bits - 1) as u8])?;
+
+        let mut table = vec![0u8; 3 << table_bits];
+        for (entry, color) in table.chunks_exact_mut(3).zip(&palette)
+        {
+            entry.copy_from_slice(color);
+        }
+        self.out.write_all(&table)?;
+
+        let min_code_size = table_bits.max(2);
+        self.out.write_all(&[min_code_size as u8])?;
+        for block in lzw_encode(&indices, min_code_size).chunks(0xFF)
+        {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0x00])
+    }
+}
+
+// Exact colors when the frame has at most 256 of them, which covers all
+// DMG frames and nearly all CGB ones. Otherwise falls back to RGB 3-3-2.
+fn quantize(image : &Image) -> (Vec<[u8; 3]>, Vec<u8>)
+{
+    let mut palette : Vec<[u8; 3]>         = Vec::new();
+    let mut lookup  : HashMap<[u8; 3], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(image.width * image.height);
+
+    for pixel in image.data.chunks_exact(3)
+    {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        match lookup.get(&color)
+        {
+            Some(&index) => indices.push(index),
+            None if palette.len() < 256 =>
+            {
+                lookup.insert(color, palette.len() as u8);
+                indices.push(palette.len() as u8);
+                palette.push(color);
+            },
+            None => return quantize_332(image)
+        }
+    }
+    (palette, indices)
+}
+
+fn quantize_332(image : &Image) -> (Vec<[u8; 3]>, Vec<u8>)
+{
+    let expand = |value : u8, bits : u32| -> u8 { ((value as u32 * 255) / ((1 << bits) - 1)) as u8 };
+    let palette = (0..=255u8).map(|i| [expand(i >> 5, 3), expand((i >> 2) & 0x7, 3), expand(i & 0x3, 2)])
+                             .collect();
+    let indices = image.data.chunks_exact(3)
+                            .map(|p| (p[0] & 0xE0) | ((p[1] >> 5) << 2) | (p[2] >> 6))
+                            .collect();
+    (palette, indices)
+}
+
+// Variable width LZW as GIF uses it, codes packed LSB first.
+fn lzw_encode(indices : &[u8], min_code_size : u32) -> Vec<u8>
+{
+    let clear = 1u16 << min_code_size;
+    let eoi   = clear + 1;
+
+    let mut out        = Vec::new();
+    let mut accum      = 0u32;
+    let mut bits       = 0u32;
+    let mut emit       = |code : u16, size : u32, out : &mut Vec<u8>|
+    {
+        accum |= (code as u32) << bits;
+        bits  += size;
+        while bits >= 8
+        {
+            out.push(accum as u8);
+            accum >>= 8;
+            bits   -= 8;
+        }
+    };
+
+    let mut dict      : HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next      = eoi + 1;
+    let mut code_size = min_code_size + 1;
+
+    emit(clear, code_size, &mut out);
+
+    let Some((&first, rest)) = indices.split_first() else
+    {
+        emit(eoi, code_size, &mut out);
+        emit(0, 7, &mut out);
+        return out;
+    };
+
+    let mut prefix = first as u16;
+    for &index in rest
+    {
+        if let Some(&code) = dict.get(&(prefix, index))
+        {
+            prefix = code;
+            continue;
+        }
+
+        emit(prefix, code_size, &mut out);
+        if next < (1 << MAX_CODE_SIZE)
+        {
+            dict.insert((prefix, index), next);
+            next += 1;
+            if next > (1 << code_size) && code_size < MAX_CODE_SIZE
+            {
+                code_size += 1;
+            }
+        }
+        else
+        {
+            emit(clear, code_size, &mut out);
+            dict.clear();
+            next      = eoi + 1;
+            code_size = min_code_size + 1;
+        }
+        prefix = index as u16;
+    }
+
+    emit(prefix, code_size, &mut out);
+    // The decoder counts the last code too.
+    if next >= (1 << code_size) && code_size < MAX_CODE_SIZE
+    {
+        code_size += 1;
+    }
+    emit(eoi, code_size, &mut out);
+    // Flush the partial byte.
+    emit(0, 7, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::image::ColorType;
+
+    // Plain GIF LZW decoder. Returns the indices and how many clear codes
+    // it saw.
+    fn lzw_decode(data : &[u8], min_code_size : u32) -> (Vec<u8>, usize)
+    {
+        let clear = 1usize << min_code_size;
+        let eoi   = clear + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..clear + 2).map(|i| vec![i as u8]).collect() };
+
+        let mut dict      = reset();
+        let mut code_size = min_code_size + 1;
+        let mut prev : Option<Vec<u8>> = None;
+        let mut out       = Vec::new();
+        let mut clears    = 0;
+        let mut position  = 0usize;
+
+        loop
+        {
+            let code = (0..code_size).fold(0usize, |code, bit|
+            {
+                let at = position + bit as usize;
+                code | ((((data[at / 8] >> (at % 8)) & 1) as usize) << bit)
+            });
+            position += code_size as usize;
+
+            if code == clear
+            {
+                dict      = reset();
+                code_size = min_code_size + 1;
+                prev      = None;
+                clears   += 1;
+                continue;
+            }
+            if code == eoi
+            {
+                return (out, clears);
+            }
+
+            let entry = match (dict.get(code), &prev)
+            {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(prev)) if code == dict.len() => [prev.as_slice(), &prev[..1]].concat(),
+                _ => panic!("bad code {} with {} entries", code, dict.len())
+            };
+            out.extend_from_slice(&entry);
+
+            if let Some(prev) = prev
+            {
+                if dict.len() < 1 << MAX_CODE_SIZE
+                {
+                    dict.push([prev.as_slice(), &entry[..1]].concat());
+                    if dict.len() == 1 << code_size && code_size < MAX_CODE_SIZE
+                    {
+                        code_size += 1;
+                    }
+                }
+            }
+            prev = Some(entry);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trip()
+    {
+        // Noise fills the 4096 entry table several times over.
+        let mut seed  = 0x1234_5678u32;
+        let noise : Vec<u8> = (0..40000).map(|_|
+        {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 24) as u8
+        }).collect();
+        let (decoded, clears) = lzw_decode(&lzw_encode(&noise, 8), 8);
+        assert_eq!(decoded, noise);
+        assert!(clears > 1);
+
+        // Long runs of few colors, like a DMG frame.
+        let runs : Vec<u8> = (0..23040).map(|i| ((i / 7) % 4) as u8).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&runs, 2), 2).0, runs);
+
+        let (decoded, clears) = lzw_decode(&lzw_encode(&[], 2), 2);
+        assert!(decoded.is_empty());
+        assert_eq!(clears, 1);
+    }
+
+    #[test]
+    fn quantize_keeps_exact_colors()
+    {
+        let mut image = Image::new(4, 1, ColorType::RGB);
+        image.put(1, 0, [0x10, 0x20, 0x30]);
+        image.put(3, 0, [0x10, 0x20, 0x30]);
+        let (palette, indices) = quantize(&image);
+        assert_eq!(palette, vec![[0, 0, 0], [0x10, 0x20, 0x30]]);
+        assert_eq!(indices, vec![0, 1, 0, 1]);
+
+        // 257 colors fall back to RGB 3-3-2.
+        let mut image = Image::new(257, 1, ColorType::RGB);
+        (0..257).for_each(|x| image.put(x, 0, [x as u8, (x >> 8) as u8, 0xFF]));
+        let (palette, indices) = quantize(&image);
+        assert_eq!(palette.len(), 256);
+        assert_eq!(indices[0xE0], 0xE0 | 0x03);
+        assert_eq!(palette[0xE3], [0xFF, 0x00, 0xFF]);
+    }
+}
@@ -0,0 +1,166 @@
+pub mod gif;
+pub mod y4m;
+
+use std::io;
+use std::path::Path;
+
+use crate::apu::CAPTURE_CHANNELS;
+use crate::audio;
+use crate::image::Image;
+use crate::ppu::DOTS_PER_FRAME;
+use crate::timer::CLOCK_HZ;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum VideoFormat
+{
+    Y4M,
+    GIF
+}
+
+impl VideoFormat
+{
+    pub fn from_path(path : &str) -> Option<Self>
+    {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str()
+        {
+            "y4m" => Some(VideoFormat::Y4M),
+            "gif" => Some(VideoFormat::GIF),
+            _     => None
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+enum Encoder
+{
+    Y4M(y4m::Writer),
+    GIF(gif::Writer)
+}
+
+// Writes every frame pushed to it. The stream is complete once finish()
+// returns, dropping a Recorder early may leave a GIF without its trailer.
+// Neither format carries sound, so the optional audio track goes to a WAV
+// file next to the video, run.y4m and run.wav, starting on the same frame.
+pub struct Recorder
+{
+    path    : String,
+    scale   : usize,
+    frames  : u64,
+    size    : Option<(usize, usize)>,
+    encoder : Encoder,
+    audio   : Option<audio::Recorder>
+}
+
+impl Recorder
+{
+    pub fn create(path : &str, scale : usize, audio_rate : Option<u32>) -> io::Result<Self>
+    {
+        let format = VideoFormat::from_path(path).ok_or_else(||
+        {
+            io::Error::new(io::ErrorKind::InvalidInput, "unknown video format, expected .y4m or .gif")
+        })?;
+
+        let encoder = match format
+        {
+            VideoFormat::Y4M => Encoder::Y4M(y4m::Writer::create(path)?),
+            VideoFormat::GIF => Encoder::GIF(gif::Writer::create(path)?)
+        };
+
+        let audio = match audio_rate
+        {
+            Some(rate) => Some(audio::Recorder::create(&audio_path(path), rate, false)?),
+            None       => None
+        };
+
+        Ok(Recorder
+        {
+            path    : path.to_string(),
+            scale   : scale.max(1),
+            frames  : 0,
+            size    : None,
+            encoder,
+            audio
+        })
+    }
+
+    pub fn path(&self) -> &str
+    {
+        &self.path
+    }
+
+    pub fn scale(&self) -> usize
+    {
+        self.scale
+    }
+
+    pub fn frames(&self) -> u64
+    {
+        self.frames
+    }
+
+    pub fn audio_path(&self) -> Option<&str>
+    {
+        self.audio.as_ref().map(|audio| audio.path())
+    }
+
+    pub fn audio_rate(&self) -> Option<u32>
+    {
+        self.audio.as_ref().map(|audio| audio.rate())
+    }
+
+    // Captured APU frames, ignored without an audio track.
+    pub fn push_audio(&mut self, frames : &[[f32; CAPTURE_CHANNELS]]) -> io::Result<()>
+    {
+        match &mut self.audio
+        {
+            Some(audio) => audio.push(frames),
+            None        => Ok(())
+        }
+    }
+
+    // Expects RGB images of the same size for the whole recording.
+    pub fn push(&mut self, image : &Image) -> io::Result<()>
+    {
+        let size = (image.width, image.height);
+        if *self.size.get_or_insert(size) != size
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame size changed during recording"));
+        }
+
+        self.frames += 1;
+        match &mut self.encoder
+        {
+            Encoder::Y4M(writer) => writer.push(image),
+            Encoder::GIF(writer) => writer.push(image)
+        }
+    }
+
+    pub fn finish(self) -> io::Result<u64>
+    {
+        match self.encoder
+        {
+            Encoder::Y4M(writer) => writer.finish()?,
+            Encoder::GIF(writer) => writer.finish()?
+        }
+        if let Some(audio) = self.audio
+        {
+            audio.finish()?;
+        }
+        Ok(self.frames)
+    }
+}
+
+// run.y4m becomes run.wav.
+pub fn audio_path(path : &str) -> String
+{
+    Path::new(path).with_extension("wav").to_string_lossy().into_owned()
+}
+
+// Start of frame n in hundredths of a second, rounded. Frames run at
+// CLOCK_HZ / DOTS_PER_FRAME, about 59.73 Hz.
+pub fn frame_centiseconds(frame : u64) -> u64
+{
+    let ticks = frame * DOTS_PER_FRAME as u64 * 100;
+    (ticks + CLOCK_HZ as u64 / 2) / CLOCK_HZ as u64
+}
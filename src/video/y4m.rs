@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+
+use crate::image::Image;
+use crate::ppu::DOTS_PER_FRAME;
+use crate::timer::CLOCK_HZ;
+
+// Uncompressed YUV4MPEG2, 4:4:4 so no chroma is lost. The header is
+// written with the first frame, once the size is known.
+pub struct Writer
+{
+    out     : BufWriter<File>,
+    started : bool,
+    planes  : Vec<u8>
+}
+
+impl Writer
+{
+    pub fn create(path : &str) -> io::Result<Self>
+    {
+        Ok(Writer
+        {
+            out     : BufWriter::new(File::create(path)?),
+            started : false,
+            planes  : Vec::new()
+        })
+    }
+
+    pub fn push(&mut self, image : &Image) -> io::Result<()>
+    {
+        if !self.started
+        {
+            writeln!(self.out, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                     image.width, image.height, CLOCK_HZ, DOTS_PER_FRAME)?;
+            self.started = true;
+        }
+
+        let rgb    = image.to_rgb();
+        let pixels = rgb.width * rgb.height;
+        self.planes.resize(pixels * 3, 0);
+
+        let (y, uv) = self.planes.split_at_mut(pixels);
+        let (u, v)  = uv.split_at_mut(pixels);
+        for (i, pixel) in rgb.data.chunks_exact(3).enumerate()
+        {
+            [y[i], u[i], v[i]] = rgb_to_yuv(pixel[0], pixel[1], pixel[2]);
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&self.planes)
+    }
+
+    pub fn finish(mut self) -> io::Result<()>
+    {
+        self.out.flush()
+    }
+}
+
+// BT.601, limited range.
+fn rgb_to_yuv(r : u8, g : u8, b : u8) -> [u8; 3]
+{
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    [y as u8, u as u8, v as u8]
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::image::ColorType;
+    use crate::test_support::temp_path;
+
+    #[test]
+    fn header_and_planes()
+    {
+        let path      = temp_path("frames.y4m");
+        let mut image = Image::new(2, 1, ColorType::RGB);
+        image.put(1, 0, [0xFF, 0xFF, 0xFF]);
+
+        let mut writer = Writer::create(&path).unwrap();
+        writer.push(&image).unwrap();
+        writer.push(&image).unwrap();
+        writer.finish().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let header = format!("YUV4MPEG2 W2 H1 F{}:{} Ip A1:1 C444\n", CLOCK_HZ, DOTS_PER_FRAME);
+        let frame  = [b"FRAME\n".as_slice(), &[16, 235, 128, 128, 128, 128]].concat();
+        assert_eq!(data, [header.as_bytes(), &frame, &frame].concat());
+    }
+}